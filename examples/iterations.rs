@@ -0,0 +1,76 @@
+use std::env;
+
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+
+use sv_challenge::registry::{AnySolver, Levels, SolverKind};
+use sv_challenge::stats::Stats;
+use sv_challenge::topology::Line1D;
+use sv_challenge::RAIN_DENSITY;
+
+/// Compared algorithms.
+/// Their results are compared with exact solution of `v3` - `pq` is an approximation of the same flow rules,
+/// it differs from `v3` more than `v2` does (and the difference does not decrease with precision).
+const SOLVERS: [SolverKind; 3] = [SolverKind::V1, SolverKind::V2, SolverKind::Pq];
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 2 {
+        eprintln!("Usage: iterations <points_num> [upper_bound=100] [steps=3] [seed=0]");
+        return;
+    }
+    let points_num = args[1].parse::<usize>().expect("First argument should be integer");
+    let upper_bound = if args.len() >= 3 {
+        args[2].parse::<usize>().expect("Second argument should be integer")
+    } else { 100 };
+    let steps = if args.len() >= 4 {
+        args[3].parse::<usize>().expect("Third argument should be integer")
+    } else { 3 };
+    let seed = if args.len() >= 5 {
+        args[4].parse::<u64>().expect("Fourth argument should be integer")
+    } else { 0 };
+    if points_num < 2 { panic!("Wrong points_num argument") }
+    if !(4..=1000).contains(&upper_bound) { panic!("Wrong upper_bound argument") }
+
+    // The same random landscape for every algorithm.
+    let mut rng = StdRng::seed_from_u64(seed);
+    let points: Vec<f64> = (0..points_num).map(|_| rng.gen_range(0, upper_bound + 1) as f64).collect();
+
+    // Exact water levels after every step.
+    let mut exact = AnySolver::create(SolverKind::V3, points.clone(), Line1D::new(points_num)).expect("Heights are finite");
+    let reference: Vec<Vec<f64>> = (0..steps).map(|_| levels(exact.rain_uniform(RAIN_DENSITY, true).expect("Simulation failed"))).collect();
+
+    println!("{:<6} {:>6} {:>12} {:>14} {:>12} {:>12} {:>14}", "solver", "step", "iterations", "visits", "updates", "time [ms]", "diff from v3");
+    for kind in &SOLVERS {
+        let mut landscape = AnySolver::create(*kind, points.clone(), Line1D::new(points_num)).expect("Heights are finite");
+        let mut total = Stats::default();
+        let mut max_diff: f64 = 0.0;
+        for (step, exact) in reference.iter().enumerate() {
+            let diff = max_diff_from(exact, landscape.rain_uniform(RAIN_DENSITY, true).expect("Simulation failed"));
+            let stats = landscape.stats();
+            print_stats(kind.name(), &(step + 1).to_string(), &stats, diff);
+            total += stats;
+            max_diff = max_diff.max(diff);
+        }
+        print_stats(kind.name(), "total", &total, max_diff);
+    }
+}
+
+/// Returns water levels of `f64` based solver.
+fn levels(levels: Levels) -> Vec<f64> {
+    match levels {
+        Levels::F64(levels) => levels.to_vec(),
+        #[allow(unreachable_patterns)]
+        _ => panic!("f64 levels expected"),
+    }
+}
+
+/// Returns the biggest difference of water levels from `exact` ones.
+fn max_diff_from(exact: &[f64], result: Levels) -> f64 {
+    exact.iter().zip(levels(result)).map(|(e, r)| (e - r).abs()).fold(0.0, f64::max)
+}
+
+fn print_stats(solver: &str, step: &str, stats: &Stats, diff: f64) {
+    println!("{:<6} {:>6} {:>12} {:>14} {:>12} {:>12.1} {:>14.4}", solver, step, stats.iterations, stats.visits, stats.updates,
+             stats.duration.as_secs_f64() * 1000.0, diff);
+}
@@ -13,7 +13,7 @@ fn main() {
         args[2].parse::<usize>().expect("Second argument should be integer")
     } else { 100 };
     if points_num < 2 { panic!("Wrong points_num argument") }
-    if upper_bound < 4 || upper_bound > 1000 { panic!("Wrong upper_bound argument") }
+    if !(4..=1000).contains(&upper_bound) { panic!("Wrong upper_bound argument") }
 
     let mut rng = rand::thread_rng();
     for _ in 0..points_num {
@@ -0,0 +1,96 @@
+/*!
+ * Mass balance - checks that water is neither created nor lost by rounding errors during simulation.
+ */
+
+use std::io::Write;
+
+use crate::error::Result;
+
+/// Mass balance of one simulation step - amounts (volumes) of water.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MassBalance {
+    /// Simulated time (in hours) at the end of step.
+    pub time: f64,
+    /// Rain added to landscape during step.
+    pub rain: f64,
+    /// Water which left landscape (thru open boundaries or drains) during step.
+    pub outflow: f64,
+    /// Water lost by infiltration and evaporation during step.
+    pub loss: f64,
+    /// Water stored in landscape at the end of step.
+    pub stored: f64,
+    /// Water created (positive) or lost (negative) during step: `stored - (previous stored + rain - outflow - loss)`.
+    pub error: f64,
+    /// Sum of errors since the beginning of simulation (drift).
+    pub drift: f64,
+}
+
+impl MassBalance {
+    /// Header of table written by `write_row()`.
+    pub const HEADER: &'static str = "time, rain, outflow, loss, stored, error, drift";
+
+    /// Writes balance as one line of comma separated values.
+    pub fn write_row(&self, output: &mut impl Write) -> Result<()> {
+        writeln!(output, "{}, {}, {}, {}, {}, {:e}, {:e}", self.time, self.rain, self.outflow, self.loss, self.stored, self.error, self.drift)?;
+        Ok(())
+    }
+}
+
+/// Records mass balance of every simulation step.
+#[derive(Debug, Clone, Default)]
+pub struct MassLedger {
+    /// Water stored in landscape at the end of last recorded step.
+    stored: f64,
+    entries: Vec<MassBalance>,
+}
+
+impl MassLedger {
+    /// Creates ledger for landscape which initially stores `stored` amount of water (usually 0).
+    pub fn new(stored: f64) -> Self {
+        MassLedger { stored, entries: Vec::new() }
+    }
+
+    /// Records step which ended at `time` and returns its balance.
+    /// Values are usually taken from `Solver::rain_added()`, `outflow()`, `loss()` and `stored()` after `rain()` call.
+    pub fn record(&mut self, time: f64, rain: f64, outflow: f64, loss: f64, stored: f64) -> &MassBalance {
+        let error = stored - (self.stored + rain - outflow - loss);
+        let drift = self.drift() + error;
+        self.stored = stored;
+        self.entries.push(MassBalance { time, rain, outflow, loss, stored, error, drift });
+        self.entries.last().unwrap()
+    }
+
+    /// Returns balances of all recorded steps.
+    pub fn entries(&self) -> &[MassBalance] {
+        &self.entries
+    }
+
+    /// Returns sum of errors of all recorded steps.
+    pub fn drift(&self) -> f64 {
+        self.entries.last().map_or(0.0, |b| b.drift)
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////////////////////////
+// Tests
+//
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn record() {
+        let mut ledger = MassLedger::new(0.0);
+        assert_eq!(ledger.record(1.0, 6.0, 1.0, 0.5, 4.5).error, 0.0);
+        let balance = *ledger.record(2.0, 6.0, 0.0, 0.0, 10.75);
+        assert_eq!((balance.error, balance.drift), (0.25, 0.25));
+        ledger.record(3.0, 0.0, 1.0, 0.0, 9.25);
+        assert_eq!(ledger.drift(), -0.25);
+        assert_eq!(ledger.entries().len(), 3);
+
+        let mut out = Vec::new();
+        ledger.entries()[1].write_row(&mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "2, 6, 0, 0, 10.75, 2.5e-1, 2.5e-1\n");
+    }
+}
@@ -0,0 +1,150 @@
+/*!
+ * Errors returned by library - reading of input data, parameters of simulation, solvers and verification mode.
+ */
+
+use std::error;
+use std::fmt;
+use std::io;
+
+use crate::verify::Report;
+
+/// Result type of library functions.
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Kind of input data (used in errors to tell which input is wrong).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Input {
+    /// Landscape - heights (and areas) of points.
+    Landscape,
+    /// Rain profile - amount of rain for every point.
+    Rain,
+    /// Rain schedule - rain of consecutive steps.
+    Schedule,
+    /// Edges of graph topology.
+    Edges,
+}
+
+impl Input {
+    fn name(self) -> &'static str {
+        match self {
+            Input::Landscape => "input",
+            Input::Rain => "rain",
+            Input::Schedule => "schedule",
+            Input::Edges => "edges",
+        }
+    }
+}
+
+/// Error of library.
+/// Errors of input data and parameters (all variants except `Verification`) are caused by caller,
+/// while `Verification` means that invariant of simulation was broken by solver.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    /// Line of input could not be read or value could not be parsed.
+    Parse { input: Input, line: usize, message: String },
+    /// Value read from input is not allowed (`what` describes the requirement).
+    InvalidValue { input: Input, line: usize, what: &'static str, value: f64 },
+    /// Line of input has wrong structure.
+    Format { input: Input, line: usize, message: String },
+    /// Line of input refers to point which does not exist in landscape of `len` points.
+    PointOutOfRange { input: Input, line: usize, index: usize, len: usize },
+    /// Data given for `len` points, while landscape has `expected` points.
+    SizeMismatch { what: String, len: usize, expected: usize },
+    /// Height of point is not finite.
+    NonFiniteHeight { point: usize, value: f64 },
+    /// Area of point is not positive.
+    InvalidArea { point: usize, value: f64 },
+    /// Parameter of simulation (e.g. rain profile, flow mode, solver name) is not valid.
+    InvalidArgument(String),
+    /// Requested `time` is earlier than `current` time of simulation.
+    TimeBeforeCurrent { time: f64, current: f64 },
+    /// Feature of simulation is not supported by solver.
+    Unsupported(&'static str),
+    /// Invariant of simulation was broken (verification mode).
+    Verification(Report),
+    /// Output could not be written.
+    Io { kind: io::ErrorKind, message: String },
+}
+
+impl Error {
+    /// Creates `Parse` error from error of reading or parsing `line` of `input`.
+    pub(crate) fn parse(input: Input, line: usize, err: impl fmt::Display) -> Self {
+        Error::Parse { input, line, message: err.to_string() }
+    }
+
+    /// Returns `true` if error was caused by solver (broken invariant).
+    /// Other errors are caused by input data or parameters, except `Error::Io` caused by environment (failed reading or writing).
+    pub fn is_internal(&self) -> bool {
+        matches!(self, Error::Verification(_))
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fn capitalized(input: Input) -> String {
+            let name = input.name();
+            name[..1].to_uppercase() + &name[1..]
+        }
+        match self {
+            Error::Parse { input, line, message } =>
+                write!(f, "Error at {} line number {}: {}", input.name(), line, message),
+            Error::InvalidValue { input, line, what, value } =>
+                write!(f, "{} line number {}: {}: {}", capitalized(*input), line, what, value),
+            Error::Format { input, line, message } =>
+                write!(f, "{} line number {}: {}", capitalized(*input), line, message),
+            Error::PointOutOfRange { input, line, index, len } =>
+                write!(f, "{} line number {}: point index {} out of range (landscape has {} points)", capitalized(*input), line, index, len),
+            Error::SizeMismatch { what, len, expected } =>
+                write!(f, "{} given for {} points, while landscape has {} points", what, len, expected),
+            Error::NonFiniteHeight { point, value } =>
+                write!(f, "Height of point {} must be finite: {}", point, value),
+            Error::InvalidArea { point, value } =>
+                write!(f, "Area of point {} must be positive: {}", point, value),
+            Error::InvalidArgument(message) =>
+                write!(f, "{}", message),
+            Error::TimeBeforeCurrent { time, current } =>
+                write!(f, "Time {} is earlier than current time of simulation {}", time, current),
+            Error::Unsupported(feature) =>
+                write!(f, "Solver does not support {}", feature),
+            Error::Verification(report) =>
+                write!(f, "{}", report),
+            Error::Io { message, .. } =>
+                write!(f, "{}", message),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+impl From<Report> for Error {
+    fn from(report: Report) -> Self {
+        Error::Verification(report)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Error::Io { kind: err.kind(), message: err.to_string() }
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////////////////////////
+// Tests
+//
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn display() {
+        let err = Error::Parse { input: Input::Rain, line: 2, message: "invalid float literal".into() };
+        assert_eq!(err.to_string(), "Error at rain line number 2: invalid float literal");
+        let err = Error::InvalidValue { input: Input::Schedule, line: 3, what: "invalid amount of rain", value: -1.0 };
+        assert_eq!(err.to_string(), "Schedule line number 3: invalid amount of rain: -1");
+        assert!(!err.is_internal());
+        assert!(Error::Verification(Report::new(1.0, 2)).is_internal());
+        let err = Error::from(io::Error::new(io::ErrorKind::BrokenPipe, "pipe closed"));
+        assert_eq!(err, Error::Io { kind: io::ErrorKind::BrokenPipe, message: "pipe closed".into() });
+    }
+}
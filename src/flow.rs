@@ -0,0 +1,88 @@
+/*!
+ * Flow rate laws - how fast water flows between neighbor points.
+ */
+
+use std::str::FromStr;
+
+use crate::error::{Error, Result};
+
+/// Determines how much water can flow from point to its neighbor during one step.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum FlowMode {
+    /// Flow is instantaneous - water flows until levels are equal within every step (as in problem description).
+    #[default]
+    Instant,
+    /// Amount of water flowing in one hour is proportional to difference of levels: `k * diff`.
+    Linear(f64),
+    /// Manning-like law: amount of water flowing in one hour is `k * depth^(5/3) * diff^(1/2)`,
+    /// where `depth` is amount of water over the point which water flows from.
+    Manning(f64),
+}
+
+impl FlowMode {
+    /// Returns `true` for instantaneous flow.
+    pub fn is_instant(self) -> bool {
+        self == FlowMode::Instant
+    }
+
+    /// Limits `amount` of water flowing during `dt` hours from point with `depth` water over it
+    /// to neighbor which level is lower by `diff`.
+    pub fn limit(self, amount: f64, depth: f64, diff: f64, dt: f64) -> f64 {
+        let max = match self {
+            FlowMode::Instant => return amount,
+            FlowMode::Linear(k) => k * diff * dt,
+            FlowMode::Manning(k) => k * depth.powf(5.0 / 3.0) * diff.sqrt() * dt,
+        };
+        if amount < max { amount } else { max }
+    }
+}
+
+/// Parses flow mode: `instant`, `linear:K` or `manning:K`.
+impl FromStr for FlowMode {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut parts = s.splitn(2, ':');
+        let name = parts.next().unwrap_or_default();
+        let k = match parts.next().map(str::parse::<f64>) {
+            Some(Ok(k)) if k > 0.0 && k.is_finite() => Some(k),
+            Some(Ok(k)) => return Err(Error::InvalidArgument(format!("Flow coefficient must be positive: {}", k))),
+            Some(Err(e)) => return Err(Error::InvalidArgument(format!("Invalid flow coefficient: {}", e))),
+            None => None,
+        };
+        match (name, k) {
+            ("instant", None) => Ok(FlowMode::Instant),
+            ("linear", Some(k)) => Ok(FlowMode::Linear(k)),
+            ("manning", Some(k)) => Ok(FlowMode::Manning(k)),
+            _ => Err(Error::InvalidArgument(format!("Unknown flow mode: {} (expected one of: instant, linear:K, manning:K)", s))),
+        }
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////////////////////////
+// Tests
+//
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        assert_eq!("instant".parse::<FlowMode>().unwrap(), FlowMode::Instant);
+        assert_eq!("linear:0.1".parse::<FlowMode>().unwrap(), FlowMode::Linear(0.1));
+        assert_eq!("manning:2".parse::<FlowMode>().unwrap(), FlowMode::Manning(2.0));
+        assert!("linear".parse::<FlowMode>().is_err());
+        assert!("linear:-1".parse::<FlowMode>().is_err());
+        assert!("instant:1".parse::<FlowMode>().is_err());
+    }
+
+    #[test]
+    fn limit() {
+        assert_eq!(FlowMode::Instant.limit(2.0, 1.0, 4.0, 1.0), 2.0);
+        assert_eq!(FlowMode::Linear(0.25).limit(2.0, 1.0, 4.0, 1.0), 1.0);
+        assert_eq!(FlowMode::Linear(0.25).limit(2.0, 1.0, 4.0, 0.5), 0.5);
+        assert_eq!(FlowMode::Linear(0.25).limit(0.5, 1.0, 4.0, 1.0), 0.5);
+        assert_eq!(FlowMode::Manning(0.5).limit(2.0, 1.0, 4.0, 1.0), 1.0);
+    }
+}
@@ -0,0 +1,146 @@
+/*!
+ * Reading landscape definition from text input.
+ */
+
+use std::io::BufRead;
+
+use crate::error::{Error, Input, Result};
+
+/// Reads landscape definition: one point height in one line.
+/// Heights may be negative (e.g. relative to sea level), but must be finite.
+/// Reading is finished on end-of-file or empty line.
+pub fn read_points(mut reader: impl BufRead) -> Result<Vec<f64>> {
+    let mut points = Vec::new();
+
+    let mut buf = String::new();
+    loop {
+        match reader.read_line(&mut buf) {
+            Ok(n) if n > 1 => {
+                match buf.trim().parse::<f64>() {
+                    Ok(p) => {
+                        if !p.is_finite() { return Err(Error::InvalidValue { input: Input::Landscape, line: points.len() + 1, what: "height must be finite", value: p }); }
+                        points.push(p);
+                    },
+                    Err(e) => return Err(Error::parse(Input::Landscape, points.len() + 1, e)),
+                }
+            },
+            Ok(_) => break,
+            Err(e) => return Err(Error::parse(Input::Landscape, points.len() + 1, e)),
+        }
+        buf.clear();
+    }
+    Ok(points)
+}
+
+/// Reads landscape definition with areas of points: one point in one line - its height optionally followed by its area
+/// (separated by space or comma, 1 by default).
+/// Reading is finished on end-of-file or empty line.
+/// Returns heights and areas of points.
+pub fn read_sections(mut reader: impl BufRead) -> Result<(Vec<f64>, Vec<f64>)> {
+    let mut points = Vec::new();
+    let mut areas = Vec::new();
+
+    let mut buf = String::new();
+    loop {
+        let line = points.len() + 1;
+        match reader.read_line(&mut buf) {
+            Ok(n) if n > 1 => {
+                let mut values = buf.split(|c: char| c == ',' || c.is_whitespace()).filter(|v| !v.is_empty()).map(str::parse::<f64>);
+                match (values.next(), values.next(), values.next()) {
+                    (Some(Ok(p)), area, None) => {
+                        if !p.is_finite() { return Err(Error::InvalidValue { input: Input::Landscape, line, what: "height must be finite", value: p }); }
+                        let a = match area {
+                            Some(Ok(a)) if a > 0.0 && a.is_finite() => a,
+                            Some(Ok(a)) => return Err(Error::InvalidValue { input: Input::Landscape, line, what: "area must be positive", value: a }),
+                            Some(Err(e)) => return Err(Error::parse(Input::Landscape, line, e)),
+                            None => 1.0,
+                        };
+                        points.push(p);
+                        areas.push(a);
+                    },
+                    (Some(Err(e)), _, _) => return Err(Error::parse(Input::Landscape, line, e)),
+                    _ => return Err(Error::Format { input: Input::Landscape, line, message: "expected height and optional area".into() }),
+                }
+            },
+            Ok(_) => break,
+            Err(e) => return Err(Error::parse(Input::Landscape, line, e)),
+        }
+        buf.clear();
+    }
+    Ok((points, areas))
+}
+
+/// Reads 2D grid landscape definition: one row of grid in one line, heights separated by spaces or commas.
+/// Reading is finished on end-of-file or empty line.
+/// Returns heights of points (row by row) and number of columns.
+pub fn read_grid(mut reader: impl BufRead) -> Result<(Vec<f64>, usize)> {
+    let mut points = Vec::new();
+    let mut cols = 0;
+
+    let mut buf = String::new();
+    let mut line = 0;
+    loop {
+        line += 1;
+        match reader.read_line(&mut buf) {
+            Ok(n) if n > 1 => {
+                let row_start = points.len();
+                for v in buf.split(|c: char| c == ',' || c.is_whitespace()).filter(|v| !v.is_empty()) {
+                    match v.parse::<f64>() {
+                        Ok(p) => {
+                            if !p.is_finite() { return Err(Error::InvalidValue { input: Input::Landscape, line, what: "height must be finite", value: p }); }
+                            points.push(p);
+                        },
+                        Err(e) => return Err(Error::parse(Input::Landscape, line, e)),
+                    }
+                }
+                let row_len = points.len() - row_start;
+                if line == 1 {
+                    cols = row_len;
+                } else if row_len != cols {
+                    return Err(Error::Format { input: Input::Landscape, line, message: format!("row has {} points, while previous rows have {}", row_len, cols) });
+                }
+            },
+            Ok(_) => break,
+            Err(e) => return Err(Error::parse(Input::Landscape, line, e)),
+        }
+        buf.clear();
+    }
+    Ok((points, cols))
+}
+
+///////////////////////////////////////////////////////////////////////////////////////////////////
+// Tests
+//
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn points() {
+        assert_eq!(read_points("3\n1.5\n6\n\n4\n".as_bytes()).unwrap(), vec![3.0, 1.5, 6.0]);
+        assert_eq!(read_points("3\n-1.5\n".as_bytes()).unwrap(), vec![3.0, -1.5]);
+        let err = read_points("3\nNaN\n".as_bytes()).unwrap_err();
+        assert_eq!(err.to_string(), "Input line number 2: height must be finite: NaN");
+        assert!(read_points("3\nx\n".as_bytes()).unwrap_err().to_string().starts_with("Error at input line number 2:"));
+    }
+
+    #[test]
+    fn sections() {
+        assert_eq!(read_sections("3\n1.5 2\n6,0.5\n\n4\n".as_bytes()).unwrap(), (vec![3.0, 1.5, 6.0], vec![1.0, 2.0, 0.5]));
+        assert!(read_sections("-inf 1\n".as_bytes()).is_err());
+        let err = read_sections("3\n1 0\n".as_bytes()).unwrap_err();
+        assert_eq!(err.to_string(), "Input line number 2: area must be positive: 0");
+        let err = read_sections("3 1 1\n".as_bytes()).unwrap_err();
+        assert_eq!(err.to_string(), "Input line number 1: expected height and optional area");
+    }
+
+    #[test]
+    fn grid() {
+        assert_eq!(read_grid("3 9,1\n9 1 2\n".as_bytes()).unwrap(), (vec![3.0, 9.0, 1.0, 9.0, 1.0, 2.0], 3));
+        assert_eq!(read_grid("-3 9\n".as_bytes()).unwrap(), (vec![-3.0, 9.0], 2));
+        assert!(read_grid("3 inf\n".as_bytes()).is_err());
+        let err = read_grid("3 9\n9\n".as_bytes()).unwrap_err();
+        assert_eq!(err.to_string(), "Input line number 2: row has 1 points, while previous rows have 2");
+    }
+}
@@ -0,0 +1,491 @@
+/*!
+ * Common part of iterative solvers (`v1`, `v2`, `mt`, `wl`, `pq`): points of landscape, rules of water flow between them,
+ * rain and losses, verification mode and statistics.
+ * Solvers differ only in which points are evaluated in one iteration of the main loop, in which order,
+ * and when calculated flows are applied - this is determined by their `Scheduler`.
+ */
+
+use std::time::Instant;
+
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+use crate::error::{Error, Result};
+
+use crate::{check_areas, check_landscape};
+use crate::flow::FlowMode;
+use crate::losses::Losses;
+use crate::stats::Stats;
+use crate::topology::{Boundary, Line1D, Topology};
+use crate::verify::{self, Flow, Report, Violation, VOLUME_TOLERANCE};
+
+/// Base unclehood type used for calculations during simulation by iterative solvers.
+pub type PointHeight = f64;
+
+/// If water level is less than this value water does not flow from point to point.
+/// Note: Placing 0.0 here may cause program to fall into infinite loop because of rounding errors.
+const VISCOSITY_COEF: PointHeight = 0.01;
+
+/// Used in place of neighbor index for water flowing outside of landscape.
+pub(crate) const OUTSIDE: usize = usize::MAX;
+
+/// Flow of water from point to its neighbor (or outside of landscape).
+#[derive(Debug, Clone)]
+pub struct WaterUpdate {
+    pub(crate) from_idx: usize,
+    pub(crate) to_idx: usize,
+    pub(crate) water: PointHeight,
+}
+
+/// Determines order in which points are evaluated during one iteration of the main loop of `stabilize_water()`.
+pub trait Scheduler<T: Topology>: Sized {
+    /// Whether limited flow rate (`FlowMode::Linear`, `FlowMode::Manning`) is supported.
+    const LIMITED_FLOW: bool = true;
+
+    /// Creates scheduler for given landscape (ground heights of points are known at this moment).
+    fn new(model: &Model<T>) -> Self;
+
+    /// Called before the first iteration of `stabilize_water()` (after rain has been added).
+    fn start(&mut self, _model: &Model<T>) {}
+
+    /// Runs one iteration of the main loop: evaluates points, calculates flows of water and applies them to `model`.
+    /// All applied flows are appended to `water_update` (which is empty at the beginning of iteration) -
+    /// if none is appended water is stable.
+    fn iteration(&mut self, model: &mut Model<T>, dt: f64, water_update: &mut Vec<WaterUpdate>);
+}
+
+/// Points of landscape and rules of water flow between them - state shared by solver and its scheduler.
+pub struct Model<T: Topology> {
+    pub(crate) topology: T,
+    pub(crate) points: Vec<Point>,
+    pub(crate) precision: PointHeight,
+    /// Conditions at the edge of landscape (or drains) for every point.
+    pub(crate) outlets: Vec<Boundary<PointHeight>>,
+    /// Amount of water which left landscape during last `rain()` call.
+    pub(crate) outflow: PointHeight,
+    /// Determines how fast water flows between neighbors.
+    pub(crate) flow_mode: FlowMode,
+    /// Statistics of last `stabilize_water()` call.
+    pub(crate) stats: Stats,
+}
+
+impl<T: Topology> Model<T> {
+    /// Calculates flows of water from point at `pi` index from current levels of points and appends them to `water_update`.
+    /// `send_water_to` is buffer for neighbors lower than the point.
+    pub(crate) fn calc_flows(&self, pi: usize, dt: f64, send_water_to: &mut Vec<usize>, water_update: &mut Vec<WaterUpdate>) {
+        let pw = self.points[pi].water;
+        if pw <= self.precision {
+            return;
+        }
+        send_water_to.clear();
+        let ph = self.points[pi].get_height();
+        for ni in self.topology.neighbors(pi) {
+            let nh = self.points[ni].get_height();
+            if ph > nh + self.precision {
+                send_water_to.push(ni);
+            }
+        }
+        let flows_outside = match self.outlets[pi] {
+            Boundary::Wall => false,
+            Boundary::Outflow => true,
+            Boundary::Reservoir(level) => ph > level + self.precision,
+        };
+        if flows_outside {
+            send_water_to.push(OUTSIDE);
+        }
+        if send_water_to.is_empty() {
+            return;
+        }
+        // Flows are calculated in volume of water, so that it is conserved when points have different areas.
+        let pa = self.points[pi].area;
+        let equal_fraction = pw * pa / send_water_to.len() as PointHeight;
+        for ni in send_water_to.iter() {
+            let (diff, flow_amt) = if *ni == OUTSIDE {
+                match self.outlets[pi] {
+                    // Level of reservoir does not change, so water may flow until levels are equal.
+                    Boundary::Reservoir(level) => {
+                        let diff = ph - level;
+                        (diff, if diff > self.precision { Some(if equal_fraction < diff * pa { equal_fraction } else { diff * pa }) } else { None })
+                    },
+                    // Outside is infinitely low - for flow rate the difference is limited by water over the point.
+                    _ => (pw, Some(equal_fraction)),
+                }
+            } else {
+                // Volume which makes levels equal: it lowers the point by `v / pa` and raises neighbor by `v / na`.
+                // Several points may send water to the same neighbor at once, so its inflow is shared by all of them -
+                // otherwise e.g. pit on 2D grid filled by 8 neighbors would overshoot and water would oscillate forever.
+                let na = self.points[*ni].area;
+                let diff = ph - self.points[*ni].get_height();
+                let senders = self.senders(*ni);
+                let equal_volume = diff * pa * na / (pa + na);
+                let equal_volume = if senders > 1 { equal_volume * 2.0 / (senders + 1) as PointHeight } else { equal_volume };
+                (diff, if diff > self.precision { Some(if equal_fraction < equal_volume { equal_fraction } else { equal_volume }) } else { None })
+            };
+            let flow_amt = flow_amt.map(|flow_amt| self.flow_mode.limit(flow_amt / pa, pw, diff, dt) * pa);
+            if let Some(flow_amt) = flow_amt {
+                water_update.push(
+                    WaterUpdate {
+                        from_idx: pi,
+                        to_idx: *ni,
+                        water: flow_amt,
+                    }
+                );
+            }
+        }
+    }
+
+    /// Returns number of neighbors of point at `idx` index which send water to it (wet and higher by more than precision).
+    fn senders(&self, idx: usize) -> usize {
+        let h = self.points[idx].get_height();
+        self.topology.neighbors(idx)
+            .filter(|ni| self.points[*ni].water > self.precision && self.points[*ni].get_height() > h + self.precision)
+            .count()
+    }
+
+    /// Applies flow of water to points (or outflow of landscape).
+    #[inline]
+    pub(crate) fn apply(&mut self, wu: &WaterUpdate) {
+        self.stats.volume_moved += wu.water;
+        self.points[wu.from_idx].drain(wu.water);
+        if wu.to_idx == OUTSIDE {
+            self.outflow += wu.water;
+        } else {
+            self.points[wu.to_idx].fill(wu.water);
+        }
+    }
+
+    /// Returns total amount (volume) of water stored in landscape.
+    fn calc_volume(&self) -> f64 {
+        self.points.iter().map(|p| p.water * p.area).sum()
+    }
+}
+
+/// Represents entire 'world' where water is raining onto and flowing down from point (section in the paper) to point.
+/// Points are evaluated in order determined by scheduler `S` - see `Landscape` types of `simul_manual_*` modules.
+pub struct IterativeLandscape<S, T: Topology = Line1D> {
+    pub(crate) model: Model<T>,
+    pub(crate) scheduler: S,
+    results: Vec<PointHeight>,
+    /// Amount of rain added to landscape during last `rain()` call.
+    rain_added: PointHeight,
+    /// Infiltration and evaporation (if enabled).
+    losses: Option<Losses>,
+    /// Amount of water lost during last `rain()` call.
+    loss: PointHeight,
+    /// Simulated time (in hours) since landscape was created.
+    time: f64,
+    /// Checks invariants of simulation (see `verify` module).
+    verify: bool,
+}
+
+impl<S: Scheduler<Line1D>> IterativeLandscape<S> {
+    /// Create Landscape object with points placed on the line (as in problem description).
+    /// `points` object is intentionally consumed to free memory as soon as possible.
+    /// In this case however it is moved and re-used as buffer for results.
+    #[allow(dead_code)]
+    pub fn create(ph: Vec<f64>) -> Result<Self> {
+        let topology = Line1D::new(ph.len());
+        Self::create_with_topology(ph, topology)
+    }
+
+    /// Create Landscape object with points placed on the line (as in problem description).
+    /// `points` object is intentionally consumed to free memory as soon as possible.
+    /// `precision` precision in which to perform simulation, the less the worse performance.
+    /// Warning: setting precision equal to zero may cause simulation hang.
+    #[allow(dead_code)]
+    pub fn create_with_precision(ph: Vec<f64>, precision: PointHeight) -> Result<Self> {
+        let topology = Line1D::new(ph.len());
+        Self::create_with_topology_and_precision(ph, topology, precision)
+    }
+}
+
+impl<S: Scheduler<T>, T: Topology> IterativeLandscape<S, T> {
+    /// Create Landscape object with points connected according to given `topology`.
+    /// `ph` must contain finite (possibly negative) height of every point of topology,
+    /// otherwise `Error::SizeMismatch` or `Error::NonFiniteHeight` is returned.
+    #[allow(dead_code)]
+    pub fn create_with_topology(ph: Vec<f64>, topology: T) -> Result<Self> {
+        check_landscape(&ph, topology.len())?;
+        let points = ph.iter().map(|h| Point::with_height(*h)).collect();
+        let outlets = (0..ph.len()).map(|i| topology.boundary(i)).collect();
+        let model = Model { topology, points, precision:VISCOSITY_COEF, outlets, outflow:0.0, flow_mode:FlowMode::Instant, stats:Stats::default() };
+        let scheduler = S::new(&model);
+        Ok(IterativeLandscape { model, scheduler, results:ph, rain_added:0.0, losses:None, loss:0.0, time:0.0, verify:false })
+    }
+
+    /// Create Landscape object with points connected according to given `topology`.
+    /// `precision` precision in which to perform simulation, the less the worse performance.
+    /// Warning: setting precision equal to zero may cause simulation hang.
+    #[allow(dead_code)]
+    pub fn create_with_topology_and_precision(ph: Vec<f64>, topology: T, precision: PointHeight) -> Result<Self> {
+        let mut landscape = Self::create_with_topology(ph, topology)?;
+        landscape.model.precision = precision;
+        Ok(landscape)
+    }
+
+    /// Marks point at `idx` index as drain - water flows from it outside of landscape (as to infinitely low neighbor).
+    #[allow(dead_code)]
+    pub fn set_drain(&mut self, idx: usize) {
+        self.model.outlets[idx] = Boundary::Outflow;
+    }
+
+    /// Function that determines how water is flowing thru landscape.
+    /// Iterations of the main loop (see `Scheduler::iteration()`) are repeated until no water flows.
+    /// Please look at `README.md` for more information.
+    fn stabilize_water(&mut self, dt: f64) -> Result<()> {
+        self.model.stats = Stats::default();
+        let start = Instant::now();
+
+        // State function and volume of water are calculated only in verification mode.
+        let (base, volume) = if self.verify { (self.lowest_ground(), self.model.calc_volume() + self.model.outflow) } else { (0.0, 0.0) };
+        let (state_lbound, mut state) = if self.verify { (self.calc_state_lbound(base), self.calc_state(base)) } else { (0.0, 0.0) };
+
+        let mut water_update = Vec::new();
+        self.scheduler.start(&self.model);
+        loop {
+            self.model.stats.iterations += 1;
+            water_update.clear();
+            self.scheduler.iteration(&mut self.model, dt, &mut water_update);
+            if water_update.is_empty() {
+                break;
+            }
+            self.model.stats.updates += water_update.len();
+
+            if self.verify {
+                state = self.check_iteration(self.model.stats.iterations, base, state, state_lbound, &water_update)?;
+            }
+
+            if !self.model.flow_mode.is_instant() {
+                // Flow rate is limited, so water flows once per step - it travels thru landscape over several steps.
+                break;
+            }
+        }
+        if self.verify {
+            self.check_volume(volume)?;
+        }
+        self.model.stats.duration = start.elapsed();
+        Ok(())
+    }
+
+    /// Checks invariants after iteration of main loop (verification mode) and returns new value of state function.
+    fn check_iteration(&self, iteration: usize, base: f64, state: f64, state_lbound: f64, water_update: &[WaterUpdate]) -> Result<f64> {
+        let points = &self.model.points;
+        let new_state = self.calc_state(base);
+        let mut report = Report::new(self.time, iteration);
+        if new_state < state_lbound {
+            report.violations.push(Violation::StateBelowBound { state: new_state, bound: state_lbound });
+        }
+        if new_state >= state {
+            report.violations.push(Violation::StateNotDecreasing { before: state, after: new_state });
+        }
+        for (point, p) in points.iter().enumerate() {
+            if p.water < -self.model.precision {
+                report.violations.push(Violation::NegativeWater { point, water: p.water });
+            }
+        }
+        if !report.is_ok() {
+            // Offending flows: from points with negative water and those after which receiving point is higher than sending one.
+            let negative = report.points();
+            report.flows = water_update.iter().filter(|wu| negative.contains(&wu.from_idx)
+                || (wu.to_idx != OUTSIDE && points[wu.to_idx].get_height() > points[wu.from_idx].get_height())
+            ).map(|wu| Flow {
+                from: wu.from_idx,
+                to: if wu.to_idx == OUTSIDE { None } else { Some(wu.to_idx) },
+                volume: wu.water,
+            }).collect();
+        }
+        report.into_result()?;
+        Ok(new_state)
+    }
+
+    /// Checks that water stored in landscape plus outflow is equal to `expected` volume
+    /// and that no point has negative amount of water (verification mode).
+    /// Water may be negative also when no flow happened (e.g. after negative rain), so all points are checked.
+    fn check_volume(&self, expected: f64) -> Result<()> {
+        let actual = self.model.calc_volume() + self.model.outflow;
+        let mut report = Report::new(self.time, 0);
+        if (actual - expected).abs() > VOLUME_TOLERANCE * expected.abs().max(1.0) {
+            report.violations.push(Violation::MassNotConserved { expected, actual });
+        }
+        for (point, p) in self.model.points.iter().enumerate() {
+            if p.water < -self.model.precision {
+                report.violations.push(Violation::NegativeWater { point, water: p.water });
+            }
+        }
+        report.into_result()
+    }
+
+    /// Returns the lowest ground level of landscape.
+    fn lowest_ground(&self) -> f64 {
+        self.model.points.iter().map(|p| p.ground).fold(f64::INFINITY, f64::min)
+    }
+
+    /// Returns value of state function (it decreases with every flow of water).
+    fn calc_state(&self, base: f64) -> f64 {
+        self.model.points.iter().map(|p| verify::state(p.get_height(), p.area, base)).sum()
+    }
+
+    /// Returns value of state function of dry landscape - state function can not be lower.
+    fn calc_state_lbound(&self, base: f64) -> f64 {
+        self.model.points.iter().map(|p| verify::state(p.ground, p.area, base)).sum()
+    }
+
+    /// Adds rain to every point and subtracts losses (step 1 of `rain()`).
+    fn add_rain(&mut self, dt: f64, rain_distr: impl Fn(usize) -> PointHeight + Sync) {
+        #[cfg(feature = "parallel")]
+        if self.losses.is_none() {
+            // Points are independent in this step, so rain is added in parallel
+            // (losses keep state of soil under points, so they are applied sequentially).
+            self.rain_added = self.model.points.par_iter_mut().enumerate().map(|(idx, p)| {
+                let rain = rain_distr(idx) * dt;
+                p.rain(rain);
+                rain * p.area
+            }).sum();
+            return;
+        }
+        for (idx, p) in self.model.points.iter_mut().enumerate() {
+            let rain = rain_distr(idx) * dt;
+            p.rain(rain);
+            self.rain_added += rain * p.area;
+            if let Some(losses) = &mut self.losses {
+                let lost = losses.apply(idx, p.water, dt);
+                p.water -= lost;
+                self.loss += lost * p.area;
+            }
+        }
+    }
+
+    /// Copies water levels of points into results buffer (step 3 of `rain()`).
+    fn collect_results(&mut self) {
+        #[cfg(feature = "parallel")]
+        self.results.par_iter_mut().zip(self.model.points.par_iter()).for_each(|(r, p)| *r = p.get_height());
+        #[cfg(not(feature = "parallel"))]
+        for (r, p) in self.results.iter_mut().zip(&self.model.points) {
+            *r = p.get_height();
+        }
+    }
+}
+
+impl<S: Scheduler<T>, T: Topology> crate::Solver for IterativeLandscape<S, T> {
+    /// Base unclehood type used for calculations during simulation in this module.
+    type PointHeight = PointHeight;
+
+    /// Simulates `dt` hours of falling rain.
+    fn rain_for(&mut self, dt: f64, rain_distr: impl Fn(usize) -> PointHeight + Sync, return_result: bool) -> Result<&[PointHeight]> {
+        self.rain_added = 0.0;
+        self.model.outflow = 0.0;
+        self.loss = 0.0;
+        self.add_rain(dt, rain_distr);
+
+        self.stabilize_water(dt)?;
+        self.time += dt;
+
+        if return_result {
+            self.collect_results();
+            Ok(&self.results[..])
+        } else {
+            Ok(&[])
+        }
+    }
+
+    /// Returns simulation precision.
+    fn precision(&self) -> PointHeight { self.model.precision }
+
+    /// Returns amount of water which left landscape during last `rain()` call.
+    fn outflow(&self) -> PointHeight { self.model.outflow }
+
+    /// Returns simulated time (in hours).
+    fn time(&self) -> f64 { self.time }
+
+    /// Returns statistics of water flow simulation during last `rain()` call.
+    fn stats(&self) -> Stats { self.model.stats }
+
+    /// Returns amount of rain added to landscape during last `rain()` call.
+    fn rain_added(&self) -> PointHeight { self.rain_added }
+
+    /// Returns amount of water stored in landscape.
+    fn stored(&self) -> PointHeight {
+        self.model.calc_volume()
+    }
+
+    /// Enables checking of invariants of simulation.
+    fn set_verify(&mut self, verify: bool) -> Result<()> {
+        self.verify = verify;
+        Ok(())
+    }
+
+    /// Sets surface area of every point.
+    fn set_areas(&mut self, areas: Vec<f64>) -> Result<()> {
+        check_areas(&areas, self.model.points.len())?;
+        for (p, a) in self.model.points.iter_mut().zip(areas) {
+            p.area = a;
+        }
+        Ok(())
+    }
+
+    /// Sets flow rate law (flow is instantaneous by default).
+    fn set_flow_mode(&mut self, mode: FlowMode) -> Result<()> {
+        if !S::LIMITED_FLOW && !mode.is_instant() {
+            return Err(Error::Unsupported("limited flow rate"));
+        }
+        self.model.flow_mode = mode;
+        Ok(())
+    }
+
+    /// Enables infiltration and evaporation.
+    fn set_losses(&mut self, losses: Losses) -> Result<()> {
+        if losses.len() != self.model.points.len() {
+            return Err(Error::SizeMismatch { what: "Losses".into(), len: losses.len(), expected: self.model.points.len() });
+        }
+        self.losses = Some(losses);
+        Ok(())
+    }
+
+    /// Returns amount of water lost during last `rain()` call.
+    fn loss(&self) -> PointHeight { self.loss }
+}
+
+/// Represents point (section) on landscape
+#[derive(Debug, Clone)]
+pub struct Point {
+    pub(crate) ground: PointHeight,
+    /// Height of water over the ground (volume of water divided by area).
+    pub(crate) water: PointHeight,
+    /// Surface area of point (section).
+    pub(crate) area: PointHeight,
+}
+
+impl Point {
+    /// Point constructor
+    fn with_height(h: PointHeight) -> Self {
+        Point {
+            ground: h,
+            water: 0.0,
+            area: 1.0,
+        }
+    }
+
+    /// Returns level of water (dry point height + water over it)
+    #[inline]
+    pub(crate) fn get_height(&self) -> PointHeight {
+        self.ground + self.water
+    }
+
+    /// Simulate `cnt` amount of water raining on point (rain volume is `cnt` multiplied by area)
+    #[inline]
+    fn rain(&mut self, cnt: PointHeight) {
+        self.water += cnt;
+    }
+
+    /// Adds `volume` of water flowing into point.
+    #[inline]
+    pub(crate) fn fill(&mut self, volume: PointHeight) {
+        self.water += volume / self.area;
+    }
+
+    /// Removes `volume` of water flowing out of point.
+    #[inline]
+    pub(crate) fn drain(&mut self, volume: PointHeight) {
+        self.water -= volume / self.area;
+    }
+}
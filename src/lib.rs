@@ -0,0 +1,183 @@
+/*!
+    Library to solve the problem of water flow in landscape and calculate water level after some amount of rain.
+    Details in the file `Rust Programming Test.pdf`.
+
+    Landscape objects (solvers) are created by `create` / `create_with_precision` (points on the line)
+    or `create_with_topology` / `create_with_topology_and_precision` functions of `Landscape` type in one of `simul_manual_*` modules
+    and implement `Solver` trait. List of available solvers is provided by `registry` module.
+
+    Please look at `README.md` for more information.  
+    License: MIT like - see `LICENSE`.  
+    Copyright (c) 2020 Grzegorz Wierzchowski.
+ */
+
+use error::{Error, Result};
+use flow::FlowMode;
+use losses::Losses;
+use stats::Stats;
+
+/// Amount of rain that falls onto one point (segment) in one step (1h).
+pub const RAIN_DENSITY: f64 = 1.0;
+
+pub mod error;
+pub mod topology;
+pub mod verify;
+pub mod balance;
+pub mod flow;
+pub mod input;
+pub mod losses;
+pub mod rain;
+pub mod stats;
+pub mod registry;
+pub mod iterative;
+pub mod simul_manual_1th_v1;
+pub mod simul_manual_1th_v2;
+#[cfg(feature = "bigdecimal")]
+pub mod simul_manual_1th_bd_v2;
+pub mod simul_manual_1th_v3;
+pub mod simul_manual_1th_ev;
+#[cfg(feature = "bigrational")]
+pub mod simul_manual_1th_br_v3;
+pub mod simul_manual_1th_pq;
+pub mod simul_manual_1th_wl;
+pub mod simul_manual_mt_v2;
+
+/// Functions required to solve problem.
+pub trait Solver {
+    /// Type that represents point height and water height.
+    /// Base unclehood type used for calculations during simulation.
+    type PointHeight: std::fmt::Debug + std::fmt::Display + From<f64> + Clone + Send + Sync;
+
+    /// Simulates `dt` hours of falling rain (all rain falls first, then water flows until it is stable).  
+    /// `rain_distr` - function which determines rain intensity (amount of water per hour) depending on point index
+    /// (with `parallel` feature it may be called from several threads).  
+    /// `return_result` - weather function should return result (water levels) or just simulate rain (empty slice is returned)
+    fn rain_for(&mut self, dt: f64, rain_distr: impl Fn(usize) -> Self::PointHeight + Sync, return_result: bool) -> Result<&[Self::PointHeight]>;
+
+    /// Simulates one step (1h in problem description) of falling rain.  
+    /// `rain_distr` - function which determines rain density (amount of water) depending on point index.  
+    /// `return_result` - weather function should return result (water levels) or just simulate rain (empty slice is returned)
+    fn rain(&mut self, rain_distr: impl Fn(usize) -> Self::PointHeight + Sync, return_result: bool) -> Result<&[Self::PointHeight]> {
+        self.rain_for(1.0, rain_distr, return_result)
+    }
+    
+    /// Default implementation in case when rain is uniform thru entire landscape (as in problem description = 1.0)
+    fn rain_uniform(&mut self, cnt: Self::PointHeight, return_result: bool) -> Result<&[Self::PointHeight]> {
+        self.rain(|_| cnt.clone(), return_result)
+    }
+
+    /// Simulates uniform rain with `density` intensity (amount per hour) until simulated time reaches `t` hours
+    /// and returns water levels at that time.
+    /// Default implementation calls `rain_for()` hour by hour (the last step may be shorter).
+    fn levels_at(&mut self, t: f64, density: Self::PointHeight) -> Result<&[Self::PointHeight]> {
+        check_time(t, self.time())?;
+        while t - self.time() > 1.0 {
+            self.rain_for(1.0, |_| density.clone(), false)?;
+        }
+        let dt = t - self.time();
+        self.rain_for(dt, |_| density.clone(), true)
+    }
+
+    /// Returns simulation precision.
+    /// If water levels difference is less than returned value, water will not flow (0 for exact simulation)
+    fn precision(&self) -> Self::PointHeight;
+
+    /// Returns amount (volume) of water which left landscape (thru open boundaries or drains) during last `rain()` call.
+    fn outflow(&self) -> Self::PointHeight;
+
+    /// Returns simulated time (in hours) - sum of durations of all `rain()` / `rain_for()` calls.
+    fn time(&self) -> f64;
+
+    /// Returns statistics of water flow simulation (iterations, visited points, flows, timing) during last `rain()` call.
+    /// Statistics are collected by iterative solvers (`v1`, `v2`, `mt`, `wl`, `pq`, `bd`) - other solvers return zeros.
+    fn stats(&self) -> Stats {
+        Stats::default()
+    }
+
+    /// Enables (or disables) verification mode: after every step of simulation invariants are checked
+    /// (state function decreases, water amounts are not negative, water volume is conserved, water is stable).
+    /// When any of them is broken `rain()` returns `Error::Verification` with `verify::Report` describing offending points.
+    /// Not every solver supports verification - in such case error is returned.
+    fn set_verify(&mut self, verify: bool) -> Result<()> {
+        if verify {
+            return Err(Error::Unsupported("verification mode"));
+        }
+        Ok(())
+    }
+
+    /// Returns amount (volume) of rain added to landscape during last `rain()` call.
+    fn rain_added(&self) -> Self::PointHeight;
+
+    /// Returns amount (volume) of water currently stored in landscape (over the ground).
+    /// Together with `rain_added()`, `outflow()` and `loss()` it allows to check mass balance - see `balance` module.
+    fn stored(&self) -> Self::PointHeight;
+
+    /// Sets surface area of every point (1 by default). Rain falling onto point and losses are scaled by its area
+    /// and flows conserve volume of water, so the same volume raises water level less on larger points.
+    /// Not every solver supports different areas - in such case error is returned.
+    fn set_areas(&mut self, areas: Vec<f64>) -> Result<()> {
+        if areas.iter().any(|a| *a != 1.0) {
+            return Err(Error::Unsupported("different areas of points"));
+        }
+        Ok(())
+    }
+
+    /// Sets flow rate law. By default flow is instantaneous - water flows until levels are equal within every step.
+    /// Not every solver supports limited flow rate - in such case error is returned.
+    fn set_flow_mode(&mut self, mode: FlowMode) -> Result<()> {
+        if !mode.is_instant() {
+            return Err(Error::Unsupported("limited flow rate"));
+        }
+        Ok(())
+    }
+
+    /// Enables water losses (infiltration and evaporation) applied at every `rain()` step.
+    /// Not every solver supports losses - in such case error is returned.
+    fn set_losses(&mut self, _losses: Losses) -> Result<()> {
+        Err(Error::Unsupported("water losses"))
+    }
+
+    /// Returns amount of water lost (infiltrated or evaporated) during last `rain()` call.
+    fn loss(&self) -> Self::PointHeight {
+        0.0.into()
+    }
+}
+
+/// Checks that heights of points are finite (NaN and infinity are not allowed, negative heights are).
+/// Landscape constructors return the same error on such heights.
+pub fn check_heights(ph: &[f64]) -> Result<()> {
+    if let Some((point, &value)) = ph.iter().enumerate().find(|(_, h)| !h.is_finite()) {
+        return Err(Error::NonFiniteHeight { point, value });
+    }
+    Ok(())
+}
+
+/// Checks heights given to landscape constructor: they must be finite and given for all `len` points of topology.
+pub(crate) fn check_landscape(ph: &[f64], len: usize) -> Result<()> {
+    if ph.len() != len {
+        return Err(Error::SizeMismatch { what: "Heights".into(), len: ph.len(), expected: len });
+    }
+    check_heights(ph)
+}
+
+/// Checks time `t` requested from simulation: it must be finite and not earlier than `current` time of simulation.
+pub(crate) fn check_time(t: f64, current: f64) -> Result<()> {
+    if !t.is_finite() {
+        return Err(Error::InvalidArgument(format!("Time must be finite: {}", t)));
+    }
+    if t < current {
+        return Err(Error::TimeBeforeCurrent { time: t, current });
+    }
+    Ok(())
+}
+
+/// Checks that `areas` of points are positive and that they are given for all `len` points of landscape.
+pub fn check_areas(areas: &[f64], len: usize) -> Result<()> {
+    if areas.len() != len {
+        return Err(Error::SizeMismatch { what: "Areas".into(), len: areas.len(), expected: len });
+    }
+    if let Some((point, &value)) = areas.iter().enumerate().find(|(_, a)| !(**a > 0.0 && a.is_finite())) {
+        return Err(Error::InvalidArea { point, value });
+    }
+    Ok(())
+}
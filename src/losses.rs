@@ -0,0 +1,129 @@
+/*!
+ * Water losses - infiltration into ground and evaporation.
+ */
+
+use crate::error::{Error, Result};
+
+/// Processes in which water leaves landscape other than flowing outside of it.
+/// Losses are applied at every `rain()` step to water standing on points (rain included) before it flows.
+/// Rates are given per hour and are scaled by duration of step.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Losses {
+    /// Amount of water which soil under every point can absorb in total.
+    infiltration_capacity: Vec<f64>,
+    /// Amount of water which soil can absorb in one hour.
+    infiltration_rate: f64,
+    /// Amount of water which evaporates from wet point in one hour.
+    evaporation_rate: f64,
+    /// Amount of water already absorbed by soil under every point.
+    absorbed: Vec<f64>,
+}
+
+impl Losses {
+    /// Creates losses for landscape of `len` points with no infiltration and no evaporation.
+    pub fn new(len: usize) -> Self {
+        Losses {
+            infiltration_capacity: vec![0.0; len],
+            infiltration_rate: 0.0,
+            evaporation_rate: 0.0,
+            absorbed: vec![0.0; len],
+        }
+    }
+
+    /// Sets infiltration: soil under every point absorbs `rate` amount of water in one hour,
+    /// until it has absorbed amount given for this point in `capacity`.
+    /// Rate must be finite and capacities not negative (infinite capacity means unlimited), otherwise water would be created.
+    pub fn set_infiltration(&mut self, capacity: Vec<f64>, rate: f64) -> Result<()> {
+        if capacity.len() != self.absorbed.len() {
+            return Err(Error::SizeMismatch { what: "Infiltration capacity".into(), len: capacity.len(), expected: self.absorbed.len() });
+        }
+        check_rate("infiltration", rate)?;
+        if let Some((point, c)) = capacity.iter().enumerate().find(|(_, c)| c.is_nan() || **c < 0.0) {
+            return Err(Error::InvalidArgument(format!("Infiltration capacity of point {} must not be negative: {}", point, c)));
+        }
+        self.infiltration_capacity = capacity;
+        self.infiltration_rate = rate;
+        Ok(())
+    }
+
+    /// Sets amount of water which evaporates from every wet point in one hour (finite, not negative).
+    pub fn set_evaporation(&mut self, rate: f64) -> Result<()> {
+        check_rate("evaporation", rate)?;
+        self.evaporation_rate = rate;
+        Ok(())
+    }
+
+    /// Returns number of points of landscape.
+    pub fn len(&self) -> usize {
+        self.absorbed.len()
+    }
+
+    /// Returns `true` if landscape has no points.
+    pub fn is_empty(&self) -> bool {
+        self.absorbed.is_empty()
+    }
+
+    /// Returns amount of water absorbed by soil under every point so far.
+    pub fn absorbed(&self) -> &[f64] {
+        &self.absorbed
+    }
+
+    /// Applies losses to `water` standing on point at `idx` index during `dt` hours.
+    /// Water infiltrates first, then the rest evaporates. Returns amount of lost water (not greater than `water`).
+    pub fn apply(&mut self, idx: usize, water: f64, dt: f64) -> f64 {
+        if water <= 0.0 {
+            return 0.0;
+        }
+        let free = (self.infiltration_capacity[idx] - self.absorbed[idx]).max(0.0);
+        let infiltrated = water.min(self.infiltration_rate * dt).min(free);
+        self.absorbed[idx] += infiltrated;
+        let evaporated = (water - infiltrated).min(self.evaporation_rate * dt);
+        infiltrated + evaporated
+    }
+}
+
+/// Checks that rate of loss is finite and not negative.
+fn check_rate(what: &str, rate: f64) -> Result<()> {
+    if rate.is_finite() && rate >= 0.0 {
+        Ok(())
+    } else {
+        Err(Error::InvalidArgument(format!("Rate of {} must be finite and not negative: {}", what, rate)))
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////////////////////////
+// Tests
+//
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn apply() {
+        let mut losses = Losses::new(2);
+        assert_eq!(losses.apply(0, 1.0, 1.0), 0.0);
+
+        losses.set_infiltration(vec![1.0, 0.0], 0.75).unwrap();
+        losses.set_evaporation(0.5).unwrap();
+        assert_eq!(losses.apply(0, 2.0, 1.0), 1.25);
+        assert_eq!(losses.apply(0, 2.0, 1.0), 0.75);
+        assert_eq!(losses.apply(0, 0.25, 1.0), 0.25);
+        assert_eq!(losses.apply(1, 2.0, 1.0), 0.5);
+        assert_eq!(losses.apply(1, 0.0, 1.0), 0.0);
+        assert_eq!(losses.absorbed(), &[1.0, 0.0]);
+    }
+
+    #[test]
+    fn invalid() {
+        let mut losses = Losses::new(2);
+        assert!(losses.set_infiltration(vec![1.0], 0.75).is_err());
+        assert!(losses.set_infiltration(vec![1.0, -1.0], 0.75).is_err());
+        assert!(losses.set_infiltration(vec![1.0, f64::NAN], 0.75).is_err());
+        assert!(losses.set_infiltration(vec![1.0, 1.0], -2.0).is_err());
+        assert!(losses.set_evaporation(-1.0).is_err());
+        assert!(losses.set_evaporation(f64::INFINITY).is_err());
+        assert_eq!(losses, Losses::new(2));
+        losses.set_infiltration(vec![1.0, f64::INFINITY], 0.75).unwrap();
+    }
+}
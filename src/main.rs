@@ -1,116 +1,483 @@
 /*!
     Program to solve the problem of water flow in landscape and calculate water level after some amount of rain.
     Details in the file `Rust Programming Test.pdf`.
- ```
-Usage:
-   sv_challenge N [<input.txt] [>output.txt]
-where:
-   N          - finish after this number of rain simulations (hours in the task description)
-   input.txt  - text file with landscape definition: one landscape point with float hight in one line
-   output.txt - results - at every simulation step (hour) a line is printed with comma separated water hights per point in input file order 
-```
-   Please look at `README.md` for more information.  
-   License: MIT like - see `LICENSE`.  
+    Run `sv_challenge --help` for description of command line parameters.
+
+   Please look at `README.md` for more information.
+   License: MIT like - see `LICENSE`.
    Copyright (c) 2020 Grzegorz Wierzchowski.
  */
 
 #[macro_use] extern crate anyhow;
 
-use std::env;
-use std::io::{stdin, stdout, Write};
+use std::fs::File;
+use std::io::{stdin, stdout, BufRead, BufReader, BufWriter, Write};
 
 use anyhow::Result;
+use clap::{App, Arg, ArgMatches};
+
+use sv_challenge::RAIN_DENSITY;
+use sv_challenge::balance::{MassBalance, MassLedger};
+use sv_challenge::flow::FlowMode;
+use sv_challenge::input::{read_grid, read_points, read_sections};
+use sv_challenge::losses::Losses;
+use sv_challenge::rain::{RainProfile, RainSchedule};
+use sv_challenge::stats::Stats;
+use sv_challenge::registry::{AnySolver, SolverKind};
+use sv_challenge::topology::{Boundary, Graph, Grid2D, Line1D, Neighborhood, Topology};
 
-/// Amount of rain that falls onto one point (segment) in one step (1h).
-const RAIN_DENSITY: f64 = 1.0;
+/// Defines command line parameters of the program.
+fn cli() -> App<'static, 'static> {
+    App::new("sv_challenge")
+        .version(env!("CARGO_PKG_VERSION"))
+        .about("Simulates water flow in landscape and prints water levels after every hour of rain.")
+        .after_help("Please look at README.md for more information.")
+        .arg(Arg::with_name("STEPS")
+            .help("Finish after this number of simulation steps (hours in the task description, see --dt)")
+            .required_unless("at")
+            .index(1))
+        .arg(Arg::with_name("TOPOLOGY")
+            .help("Topology of landscape: line (as in task description), grid4 / grid8 (2D grid with 4 / 8 neighbors), \
+                   graph (arbitrary graph - points connected by edges listed in EDGES file)")
+            .possible_values(&["line", "grid4", "grid8", "graph"])
+            .default_value("line")
+            .index(2))
+        .arg(Arg::with_name("EDGES")
+            .help("Text file with graph edges: indexes (counted from 0) of two connected points in one line")
+            .required_if("TOPOLOGY", "graph")
+            .index(3))
+        .arg(Arg::with_name("solver")
+            .short("s")
+            .long("solver")
+            .value_name("SOLVER")
+            .help("Algorithm used to solve problem (see README.md); pq is approximate - its levels may differ from exact \
+                   solution of v3 much more than precision (by up to 0.75 after 3 steps on random landscape)")
+            .possible_values(&SolverKind::ALL.iter().map(|k| k.name()).collect::<Vec<_>>())
+            .default_value(SolverKind::default().name()))
+        .arg(Arg::with_name("precision")
+            .short("p")
+            .long("precision")
+            .value_name("PRECISION")
+            .help("Precision in which to perform simulation (ignored by exact algorithms), \
+                   the less the worse performance [default: algorithm specific]"))
+        .arg(Arg::with_name("rain")
+            .short("r")
+            .long("rain")
+            .value_name("DENSITY")
+            .help("Amount of water falling onto every point in one hour [default: 1]"))
+        .arg(Arg::with_name("rain-file")
+            .long("rain-file")
+            .value_name("FILE")
+            .help("Text file with amount of water falling onto every point in one hour: \
+                   one value in one line in the same order as points in input file")
+            .conflicts_with_all(&["rain", "rain-fn"]))
+        .arg(Arg::with_name("rain-fn")
+            .long("rain-fn")
+            .value_name("PROFILE")
+            .help("Function determining amount of water falling onto every point in one hour, one of: \
+                   uniform:AMOUNT, gradient:FROM:TO[:AXIS] (changing linearly along AXIS coordinate), \
+                   gaussian:PEAK:SIGMA:C0[,C1] (storm centred at given coordinates)")
+            .conflicts_with("rain"))
+        .arg(Arg::with_name("schedule")
+            .long("schedule")
+            .value_name("FILE")
+            .help("Text file with rain schedule - one hour in one line: either intensity (multiplier of amount \
+                   of rain given by other options) or amount of rain for every point; hours after the end of schedule are dry"))
+        .arg(Arg::with_name("infiltration")
+            .long("infiltration")
+            .value_name("RATE")
+            .help("Amount of water which soil under every point absorbs in one hour (not supported by bd and br solvers)"))
+        .arg(Arg::with_name("capacity")
+            .long("capacity")
+            .value_name("AMOUNT")
+            .help("Amount of water which soil under every point can absorb in total [default: unlimited]")
+            .requires("infiltration"))
+        .arg(Arg::with_name("capacity-file")
+            .long("capacity-file")
+            .value_name("FILE")
+            .help("Text file with amount of water which soil under every point can absorb in total: \
+                   one value in one line in the same order as points in input file")
+            .requires("infiltration")
+            .conflicts_with("capacity"))
+        .arg(Arg::with_name("evaporation")
+            .long("evaporation")
+            .value_name("RATE")
+            .help("Amount of water which evaporates from every wet point in one hour (not supported by bd and br solvers)"))
+        .arg(Arg::with_name("flow")
+            .long("flow")
+            .value_name("MODE")
+            .help("Flow rate law, one of: instant (water levels equalize within every step), linear:K (flow in one hour \
+                   is K * level difference), manning:K (flow in one hour is K * depth^(5/3) * sqrt(level difference)); \
+                   limited flow rate is supported by v1, v2, mt and wl solvers [default: instant]"))
+        .arg(Arg::with_name("boundary")
+            .long("boundary")
+            .value_name("SIDE=BOUNDARY")
+            .help("Condition at the edge of landscape (may be given several times): SIDE is left, right (line, grid), \
+                   top, bottom (grid), all or point index (graph) and BOUNDARY is one of: wall (water does not flow outside), \
+                   outflow (water flows outside freely), reservoir:LEVEL (water above LEVEL flows outside) [default: all=wall]")
+            .multiple(true)
+            .number_of_values(1))
+        .arg(Arg::with_name("drain")
+            .long("drain")
+            .value_name("INDEX")
+            .help("Index (counted from 0) of point from which water flows outside of landscape (may be given several times)")
+            .multiple(true)
+            .number_of_values(1))
+        .arg(Arg::with_name("dt")
+            .long("dt")
+            .value_name("HOURS")
+            .help("Duration of one simulation step (water levels are printed after every step) [default: 1]"))
+        .arg(Arg::with_name("substeps")
+            .long("substeps")
+            .value_name("COUNT")
+            .help("Number of sub-steps in which rain is applied during one simulation step, \
+                   water flows until it is stable after every sub-step [default: 1]"))
+        .arg(Arg::with_name("at")
+            .long("at")
+            .value_name("HOURS")
+            .help("Print water levels only once - after given time of uniform rain (ev solver jumps directly to this time, \
+                   other solvers simulate it hour by hour); STEPS is then ignored and may be omitted, unless TOPOLOGY \
+                   follows it (e.g. --at 2.5 0 grid4)")
+            .conflicts_with_all(&["rain-file", "rain-fn", "schedule", "dt", "substeps", "mass-balance", "max-drift", "stats"]))
+        .arg(Arg::with_name("mass-balance")
+            .long("mass-balance")
+            .value_name("FILE")
+            .help("File to write mass balance to - at every simulation step a line with comma separated amounts of water: \
+                   time, rain, outflow, loss, stored, error (water created or lost by rounding) and drift (sum of errors)"))
+        .arg(Arg::with_name("max-drift")
+            .long("max-drift")
+            .value_name("AMOUNT")
+            .help("Stop with error when absolute value of mass balance drift exceeds given amount of water"))
+        .arg(Arg::with_name("verify")
+            .long("verify")
+            .help("Check invariants of simulation after every step (state function decreases, water amounts are not negative, \
+                   water volume is conserved, water is stable) and stop with report of offending points when any of them is broken \
+                   (not supported by ev solver)"))
+        .arg(Arg::with_name("stats")
+            .long("stats")
+            .help("Print statistics of water flow simulation (iterations, visited points, flows, volume of moved water, time) \
+                   after every simulation step and in total to stderr (collected by v1, v2, mt, wl, pq and bd solvers)"))
+        .arg(Arg::with_name("input")
+            .short("i")
+            .long("input")
+            .value_name("FILE")
+            .help("Text file with landscape definition: one landscape point with float height (optionally followed by its area) in one line, \
+                   for 2D grid: one row of grid in one line (heights separated by spaces or commas) [default: stdin]"))
+        .arg(Arg::with_name("output")
+            .short("o")
+            .long("output")
+            .value_name("FILE")
+            .help("File to write results to - at every simulation step a line with comma separated water heights \
+                   per point in input order is printed, for 2D grid: a matrix (one grid row in one line) followed by empty line \
+                   [default: stdout]"))
+}
 
-mod simul_manual_1th_v1;
-mod simul_manual_1th_v2;
-#[cfg(feature = "bigdecimal")]
-mod simul_manual_1th_bd_v2;
-// mod simul_manual_1th_v3;
+/// Simulation parameters common for all topologies.
+struct Params {
+    kind: SolverKind,
+    precision: Option<f64>,
+    rain: RainProfile,
+    schedule: Option<RainSchedule>,
+    losses: Option<LossParams>,
+    flow: FlowMode,
+    /// Conditions at the edge of landscape given for sides (or points of graph).
+    boundaries: Vec<(String, Boundary)>,
+    /// Indexes of drain points.
+    drains: Vec<usize>,
+    steps: usize,
+    /// Duration of one step in hours.
+    dt: f64,
+    substeps: usize,
+    /// Time at which water levels are printed (instead of printing them after every step).
+    at: Option<f64>,
+    /// File to write mass balance to.
+    mass_balance: Option<String>,
+    max_drift: Option<f64>,
+    /// Print simulation statistics.
+    stats: bool,
+    /// Check invariants of simulation.
+    verify: bool,
+}
 
+/// Parameters of water losses.
+struct LossParams {
+    infiltration_rate: f64,
+    /// Infiltration capacity given per point (if `None` it is equal to `capacity` for every point).
+    capacity_per_point: Option<Vec<f64>>,
+    capacity: f64,
+    evaporation_rate: f64,
+}
 
-/// Creates concrete object used to solve problem.
-fn solver_factory(points_heights: Vec<f64>) -> impl Solver {
-    // simul_manual_1th_v1::Landscape::create(points_heights)
-    simul_manual_1th_v2::Landscape::create(points_heights)
+impl LossParams {
+    /// Creates losses for landscape of `len` points.
+    fn build(&self, len: usize) -> Result<Losses> {
+        let mut losses = Losses::new(len);
+        let capacity = match &self.capacity_per_point {
+            Some(capacity) => capacity.clone(),
+            None => vec![self.capacity; len],
+        };
+        losses.set_infiltration(capacity, self.infiltration_rate)?;
+        losses.set_evaporation(self.evaporation_rate)?;
+        Ok(losses)
+    }
 }
 
 /// Program main function.
 fn main() -> Result<()> {
-    let args: Vec<String> = env::args().collect(); // TODO: use clap here
-    if args.len() < 2 {
-        bail!(
-r#"Missing command line parameter
-
-Usage:
-   {} N [<input.txt] [>output.txt]
-where:
-   N          - finish after this number of rain simulations (hours in the task description)
-   input.txt  - text file with landscape definition: one landscape point with float hight in one line
-   output.txt - results - at every simulation step (hour) a line is printed with comma separated water hights per point in input file order 
-"#, args[0]);
-    }
-
-    let steps = usize::from_str_radix(&args[1], 10)?;
-    let mut points = Vec::new();
-
-    let stdin = stdin();
-    let mut buf = String::new();
-    loop {
-        match stdin.read_line(&mut buf) {
-            Ok(n) if n > 1 => {
-                match buf.trim().parse::<f64>() {
-                    Ok(p) => {
-                        // TODO: Maybe negative is ok - check after algorithm is ready
-                        // Note: Algorithm should be ok, but negative numbers may mess-up calc_state()
-                        if p < 0.0 { bail!("Input line number {}: negative height: {} not allowed", points.len() + 1, p); }
-                        points.push(p);
-                    },
-                    Err(e) => bail!("Error at input line number {}: {}", points.len() + 1, e),
+    let matches = cli().get_matches();
+    let params = Params {
+        kind: matches.value_of("solver").unwrap_or_default().parse()?,
+        precision: parse_amount(&matches, "precision", true)?,
+        rain: match (matches.value_of("rain-file"), parse_arg(&matches, "rain-fn")?) {
+            (Some(path), _) => RainProfile::read(BufReader::new(open_file(path)?))?,
+            (None, Some(profile)) => profile,
+            (None, None) => RainProfile::Uniform(parse_amount(&matches, "rain", false)?.unwrap_or(RAIN_DENSITY)),
+        },
+        schedule: match matches.value_of("schedule") {
+            Some(path) => Some(RainSchedule::read(BufReader::new(open_file(path)?))?),
+            None => None,
+        },
+        losses: if matches.is_present("infiltration") || matches.is_present("evaporation") {
+            Some(LossParams {
+                infiltration_rate: parse_amount(&matches, "infiltration", false)?.unwrap_or(0.0),
+                capacity_per_point: match matches.value_of("capacity-file") {
+                    Some(path) => Some(read_capacity(path)?),
+                    None => None,
+                },
+                capacity: parse_amount(&matches, "capacity", false)?.unwrap_or(f64::INFINITY),
+                evaporation_rate: parse_amount(&matches, "evaporation", false)?.unwrap_or(0.0),
+            })
+        } else {
+            None
+        },
+        flow: parse_arg(&matches, "flow")?.unwrap_or_default(),
+        boundaries: match matches.values_of("boundary") {
+            Some(values) => values.map(parse_boundary).collect::<Result<_>>()?,
+            None => Vec::new(),
+        },
+        drains: match matches.values_of("drain") {
+            Some(values) => values.map(|v| match v.parse() {
+                Ok(idx) => Ok(idx),
+                Err(e) => bail!("Invalid value of drain: {} ({})", v, e),
+            }).collect::<Result<_>>()?,
+            None => Vec::new(),
+        },
+        steps: parse_arg(&matches, "STEPS")?.unwrap_or_default(),
+        dt: parse_arg(&matches, "dt")?.unwrap_or(1.0),
+        substeps: parse_arg(&matches, "substeps")?.unwrap_or(1),
+        at: parse_amount(&matches, "at", false)?,
+        mass_balance: matches.value_of("mass-balance").map(String::from),
+        max_drift: parse_amount(&matches, "max-drift", false)?,
+        stats: matches.is_present("stats"),
+        verify: matches.is_present("verify"),
+    };
+    let input: Box<dyn BufRead> = match matches.value_of("input") {
+        Some(path) => Box::new(BufReader::new(open_file(path)?)),
+        None => Box::new(BufReader::new(stdin())),
+    };
+    let output: Box<dyn Write> = match matches.value_of("output") {
+        Some(path) => Box::new(BufWriter::new(create_file(path)?)),
+        None => Box::new(BufWriter::new(stdout())),
+    };
+
+    match matches.value_of("TOPOLOGY") {
+        Some(grid @ "grid4") | Some(grid @ "grid8") => {
+            let neighborhood = if grid == "grid4" { Neighborhood::VonNeumann } else { Neighborhood::Moore };
+            let (points, cols) = read_grid(input)?;
+            let mut topology = Grid2D::new(points.len() / cols.max(1), cols, neighborhood);
+            if let [top, right, bottom, left] = side_boundaries(&params, &["top", "right", "bottom", "left"])?[..] {
+                topology.set_boundaries(top, right, bottom, left);
+            }
+            simulate(&params, points, None, topology, cols, true, output)
+        },
+        Some("graph") => {
+            let edges = matches.value_of("EDGES").unwrap_or_default();
+            let (points, areas) = read_sections(input)?;
+            let cols = points.len();
+            let mut topology = Graph::read_edges(BufReader::new(open_file(edges)?), points.len())?;
+            for (point, boundary) in &params.boundaries {
+                match point.parse::<usize>() {
+                    Ok(idx) if idx < points.len() => topology.set_boundary(idx, *boundary),
+                    _ => bail!("Invalid point of boundary: {} (sides of graph are given as point indexes less than {})", point, points.len()),
                 }
-            },
-            Ok(_) => break,
-            Err(e) => bail!("Error at input line number {}: {}", points.len() + 1, e),
-        }
-        buf.clear();
-    }
-    
-    let mut stdout = stdout();
-    let mut landscape = solver_factory(points);
-    for n in 1..=steps {
-        match landscape.rain_uniform(RAIN_DENSITY.into(), true) {
-            Ok(water_levels) => {
-                // TODO: it should be rather format!("{}", ...
-                // TODO: this may not be good, it should be reworked using IntoStr
-                stdout.write_all(format!("{:?}", water_levels).trim_matches(&['[',']'] as &[_]).as_bytes())?; 
-                stdout.write(&[b'\n'])?;
-            },
-            Err(e) => { bail!("Error during {} st/th invocation of rain(): {}", n, e); }
+            }
+            simulate(&params, points, Some(areas), topology, cols, false, output)
+        },
+        _ => {
+            let (points, areas) = read_sections(input)?;
+            let cols = points.len();
+            let mut topology = Line1D::new(points.len());
+            if let [left, right] = side_boundaries(&params, &["left", "right"])?[..] {
+                topology.set_boundaries(left, right);
+            }
+            simulate(&params, points, Some(areas), topology, cols, false, output)
+        },
+    }
+}
+
+/// Parses value of command line argument `name` if it was given.
+fn parse_arg<F: std::str::FromStr>(matches: &ArgMatches, name: &str) -> Result<Option<F>>
+where F::Err: std::fmt::Display {
+    match matches.value_of(name) {
+        Some(value) => match value.parse() {
+            Ok(value) => Ok(Some(value)),
+            Err(e) => bail!("Invalid value of {}: {} ({})", name, value, e),
+        },
+        None => Ok(None),
+    }
+}
+
+/// Parses value of command line argument `name` (amount of water, rate or precision) if it was given.
+/// Value must be finite and not negative (or positive if `positive` is set).
+fn parse_amount(matches: &ArgMatches, name: &str, positive: bool) -> Result<Option<f64>> {
+    match parse_arg::<f64>(matches, name)? {
+        Some(value) if !value.is_finite() || value < 0.0 || (positive && value == 0.0) =>
+            bail!("Invalid value of {}: {} (must be finite and {})", name, value, if positive { "positive" } else { "not negative" }),
+        value => Ok(value),
+    }
+}
+
+/// Parses condition at the edge of landscape given as `SIDE=BOUNDARY`.
+fn parse_boundary(value: &str) -> Result<(String, Boundary)> {
+    match value.split_once('=') {
+        Some((side, boundary)) => match boundary.parse() {
+            Ok(boundary) => Ok((side.to_string(), boundary)),
+            Err(e) => bail!("Invalid value of boundary: {} ({})", value, e),
+        },
+        None => bail!("Invalid value of boundary: {} (expected SIDE=BOUNDARY)", value),
+    }
+}
+
+/// Returns conditions at given `sides` of landscape (wall if not given, side `all` sets all of them).
+/// Boundaries given for other sides are rejected.
+fn side_boundaries(params: &Params, sides: &[&str]) -> Result<Vec<Boundary>> {
+    let mut boundaries = vec![Boundary::Wall; sides.len()];
+    for (side, boundary) in &params.boundaries {
+        match sides.iter().position(|s| s == side) {
+            Some(idx) => boundaries[idx] = *boundary,
+            None if side == "all" => boundaries.iter_mut().for_each(|b| *b = *boundary),
+            None => bail!("Invalid side of boundary: {} (expected one of: all, {})", side, sides.join(", ")),
         }
     }
-    Ok(())
+    Ok(boundaries)
+}
+
+/// Reads infiltration capacity of every point from file (one value in one line, finite and not negative).
+fn read_capacity(path: &str) -> Result<Vec<f64>> {
+    let capacity = read_points(BufReader::new(open_file(path)?))?;
+    if let Some((idx, c)) = capacity.iter().enumerate().find(|(_, c)| **c < 0.0) {
+        bail!("Invalid infiltration capacity in file {} at line number {}: {} (must be finite and not negative)", path, idx + 1, c);
+    }
+    Ok(capacity)
+}
+
+/// Opens file for reading with error message containing its path.
+fn open_file(path: &str) -> Result<File> {
+    match File::open(path) {
+        Ok(file) => Ok(file),
+        Err(e) => bail!("Can not open file {}: {}", path, e),
+    }
 }
 
-/// Functions required to solve problem.
-pub trait Solver {
-    /// Type that represents point height and water height.
-    /// Base unclehood type used for calculations during simulation.
-    type PointHeight: std::fmt::Debug + From<f64> + Clone; //TODO: It should be rather Display
-
-    /// Simulates one step (1h in problem description) of falling rain.  
-    /// `rain_distr` - function which determines rain density (amount of water) depending on point index.  
-    /// `return_result` - weather function should return result (water levels) or just simulate rain (empty slice is returned)
-    fn rain(&mut self, rain_distr: impl Fn(usize) -> Self::PointHeight, return_result: bool) -> Result<&[Self::PointHeight]>;
-    
-    /// Default implementation in case when rain is uniform thru entire landscape (as in problem description = 1.0)
-    fn rain_uniform(&mut self, cnt: Self::PointHeight, return_result: bool) -> Result<&[Self::PointHeight]> {
-        self.rain(|_| cnt.clone(), return_result)
-    }
-
-    /// Returns simulation precision.
-    /// If water levels difference is less than returned value, water will not flow (0 for exact simulation)
-    fn precision(&self) -> Self::PointHeight;
+/// Creates file for writing with error message containing its path.
+fn create_file(path: &str) -> Result<File> {
+    match File::create(path) {
+        Ok(file) => Ok(file),
+        Err(e) => bail!("Can not create output file {}: {}", path, e),
+    }
 }
 
+/// Runs `params.steps` rain simulations and prints water levels after each of them to `output`.
+/// `areas` of points are given only if they were read from input.
+/// Levels are printed in lines of `cols` values, when `matrix` is set every step is followed by empty line.
+fn simulate<T: Topology>(params: &Params, points: Vec<f64>, areas: Option<Vec<f64>>, topology: T, cols: usize, matrix: bool, mut output: impl Write) -> Result<()> {
+    if !(params.dt > 0.0 && params.dt.is_finite()) || params.substeps == 0 {
+        bail!("Duration of simulation step and number of sub-steps must be positive");
+    }
+    let len = topology.len();
+    let rain = params.rain.distribution(&topology)?;
+    if let Some(schedule) = &params.schedule {
+        schedule.check(topology.len())?;
+    }
+    let mut landscape = match params.precision {
+        Some(precision) => AnySolver::create_with_precision(params.kind, points, topology, precision),
+        None => AnySolver::create(params.kind, points, topology),
+    }?;
+    for &idx in &params.drains {
+        if idx >= len {
+            bail!("Invalid drain point: {} (landscape has {} points)", idx, len);
+        }
+        landscape.set_drain(idx);
+    }
+    if let Some(losses) = &params.losses {
+        landscape.set_losses(losses.build(len)?)?;
+    }
+    if let Some(areas) = areas {
+        landscape.set_areas(areas)?;
+    }
+    landscape.set_flow_mode(params.flow)?;
+    landscape.set_verify(params.verify)?;
+    if let (Some(t), RainProfile::Uniform(density)) = (params.at, &params.rain) {
+        landscape.levels_at(t, *density)?.write_rows(&mut output, cols)?;
+        output.flush()?;
+        return Ok(());
+    }
+    let mut ledger = MassLedger::new(landscape.stored());
+    let mut balance_output = match &params.mass_balance {
+        Some(path) => {
+            let mut file = BufWriter::new(create_file(path)?);
+            writeln!(file, "{}", MassBalance::HEADER)?;
+            Some(file)
+        },
+        None => None,
+    };
+    let mut total_stats = Stats::default();
+    let sub_dt = params.dt / params.substeps as f64;
+    for n in 1..=params.steps {
+        let (mut rain_added, mut outflow, mut loss) = (0.0, 0.0, 0.0);
+        let mut stats = Stats::default();
+        for k in 1..=params.substeps {
+            // Hour (line of schedule) is determined by the middle of sub-step, so that rounding errors of time do not matter.
+            let hour = (landscape.time() + sub_dt / 2.0).floor() as usize;
+            let last = k == params.substeps;
+            let result = match &params.schedule {
+                Some(schedule) => landscape.rain_for(sub_dt, |idx| schedule.amount(hour, idx, &rain), last),
+                None => landscape.rain_for(sub_dt, |idx| rain[idx], last),
+            };
+            match result {
+                Ok(water_levels) => if last {
+                    water_levels.write_rows(&mut output, cols)?;
+                    if matrix {
+                        output.write_all(b"\n")?;
+                    }
+                },
+                Err(e) => { bail!("Error during {} st/th simulation step: {}", n, e); }
+            }
+            rain_added += landscape.rain_added();
+            outflow += landscape.outflow();
+            loss += landscape.loss();
+            stats += landscape.stats();
+        }
+        if params.stats {
+            eprintln!("Step {}: {}", n, stats);
+        }
+        total_stats += stats;
+        let balance = ledger.record(landscape.time(), rain_added, outflow, loss, landscape.stored());
+        if let Some(balance_output) = &mut balance_output {
+            balance.write_row(balance_output)?;
+        }
+        if let Some(max_drift) = params.max_drift {
+            if balance.drift.abs() > max_drift {
+                bail!("Mass balance drift {:e} exceeds {:e} after {} st/th simulation step", balance.drift, max_drift, n);
+            }
+        }
+    }
+    if let Some(balance_output) = &mut balance_output {
+        balance_output.flush()?;
+    }
+    if params.stats {
+        eprintln!("Total: {}", total_stats);
+    }
+    output.flush()?;
+    Ok(())
+}
@@ -0,0 +1,290 @@
+/*!
+ * Spatial distribution of rain (rain profile) over landscape and its changes in time (rain schedule).
+ */
+
+use std::io::BufRead;
+use std::str::FromStr;
+
+use crate::error::{Error, Input, Result};
+
+use crate::topology::Topology;
+
+/// Describes how much rain falls onto every point of landscape in one step.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RainProfile {
+    /// The same amount of rain onto every point (as in problem description).
+    Uniform(f64),
+    /// Amount of rain given separately for every point (by point index).
+    PerPoint(Vec<f64>),
+    /// Amount of rain changing linearly from `from` (at first point) to `to` (at last point) along `axis` coordinate.
+    Gradient { from: f64, to: f64, axis: usize },
+    /// Storm with `peak` amount of rain at `centre` coordinates, decreasing with distance as gaussian function with `sigma` deviation.
+    Gaussian { peak: f64, sigma: f64, centre: Vec<f64> },
+}
+
+impl RainProfile {
+    /// Reads amount of rain for every point: one value in one line.
+    /// Reading is finished on end-of-file or empty line.
+    pub fn read(mut reader: impl BufRead) -> Result<Self> {
+        let mut values = Vec::new();
+
+        let mut buf = String::new();
+        loop {
+            match reader.read_line(&mut buf) {
+                Ok(n) if n > 1 => {
+                    match buf.trim().parse::<f64>() {
+                        Ok(v) => {
+                            if v < 0.0 || !v.is_finite() { return Err(Error::InvalidValue { input: Input::Rain, line: values.len() + 1, what: "invalid amount of rain", value: v }); }
+                            values.push(v);
+                        },
+                        Err(e) => return Err(Error::parse(Input::Rain, values.len() + 1, e)),
+                    }
+                },
+                Ok(_) => break,
+                Err(e) => return Err(Error::parse(Input::Rain, values.len() + 1, e)),
+            }
+            buf.clear();
+        }
+        Ok(RainProfile::PerPoint(values))
+    }
+
+    /// Calculates amount of rain for every point of `topology`.
+    /// Points of topologies without coordinates are placed on the line in index order.
+    pub fn distribution(&self, topology: &impl Topology) -> Result<Vec<f64>> {
+        let len = topology.len();
+        let coordinates = |idx| topology.coordinates(idx).unwrap_or_else(|| vec![idx]);
+        Ok(match self {
+            RainProfile::Uniform(cnt) => vec![*cnt; len],
+            RainProfile::PerPoint(values) => {
+                if values.len() != len {
+                    return Err(Error::SizeMismatch { what: "Rain profile".into(), len: values.len(), expected: len });
+                }
+                values.clone()
+            },
+            RainProfile::Gradient { from, to, axis } => {
+                let positions = (0..len)
+                    .map(|idx| coordinates(idx).get(*axis).copied())
+                    .collect::<Option<Vec<_>>>();
+                let positions = match positions {
+                    Some(positions) => positions,
+                    None => return Err(Error::InvalidArgument(format!("Rain gradient axis {} does not exist in landscape", axis))),
+                };
+                let max = positions.iter().copied().max().unwrap_or(0);
+                positions.iter()
+                    .map(|&pos| if max == 0 { *from } else { from + (to - from) * pos as f64 / max as f64 })
+                    .collect()
+            },
+            RainProfile::Gaussian { peak, sigma, centre } => {
+                let mut values = Vec::with_capacity(len);
+                for idx in 0..len {
+                    let point = coordinates(idx);
+                    if point.len() != centre.len() {
+                        return Err(Error::InvalidArgument(format!("Storm centre has {} coordinates, while landscape points have {}", centre.len(), point.len())));
+                    }
+                    let dist2: f64 = point.iter().zip(centre).map(|(&p, c)| (p as f64 - c) * (p as f64 - c)).sum();
+                    values.push(peak * (-dist2 / (2.0 * sigma * sigma)).exp());
+                }
+                values
+            },
+        })
+    }
+}
+
+/// Parses function specification of rain profile:
+/// `uniform:AMOUNT`, `gradient:FROM:TO[:AXIS]` or `gaussian:PEAK:SIGMA:C0[,C1...]`.
+impl FromStr for RainProfile {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        fn num(name: &str, v: Option<&str>) -> Result<f64> {
+            match v.map(str::parse::<f64>) {
+                Some(Ok(v)) if v.is_finite() => Ok(v),
+                Some(Ok(v)) => Err(Error::InvalidArgument(format!("Invalid {} of rain profile: {}", name, v))),
+                Some(Err(e)) => Err(Error::InvalidArgument(format!("Invalid {} of rain profile: {}", name, e))),
+                None => Err(Error::InvalidArgument(format!("Missing {} of rain profile", name))),
+            }
+        }
+        let mut parts = s.split(':');
+        let profile = match parts.next() {
+            Some("uniform") => RainProfile::Uniform(num("amount", parts.next())?),
+            Some("gradient") => RainProfile::Gradient {
+                from: num("FROM", parts.next())?,
+                to: num("TO", parts.next())?,
+                axis: match parts.next().map(str::parse::<usize>) {
+                    Some(Ok(axis)) => axis,
+                    Some(Err(e)) => return Err(Error::InvalidArgument(format!("Invalid AXIS of rain profile: {}", e))),
+                    None => 0,
+                },
+            },
+            Some("gaussian") => RainProfile::Gaussian {
+                peak: num("PEAK", parts.next())?,
+                sigma: num("SIGMA", parts.next())?,
+                centre: match parts.next() {
+                    Some(centre) => centre.split(',').map(|c| num("centre coordinate", Some(c))).collect::<Result<_>>()?,
+                    None => return Err(Error::InvalidArgument("Missing centre of rain profile".into())),
+                },
+            },
+            _ => return Err(Error::InvalidArgument(format!("Unknown rain profile: {} (expected one of: uniform:AMOUNT, gradient:FROM:TO[:AXIS], gaussian:PEAK:SIGMA:C0[,C1...])", s))),
+        };
+        if parts.next().is_some() {
+            return Err(Error::InvalidArgument(format!("Too many parameters of rain profile: {}", s)));
+        }
+        match &profile {
+            RainProfile::Uniform(v) | RainProfile::Gradient { from: v, .. } | RainProfile::Gaussian { peak: v, .. } if *v < 0.0 =>
+                return Err(Error::InvalidArgument(format!("Negative amount of rain in profile: {}", s))),
+            RainProfile::Gradient { to, .. } if *to < 0.0 => return Err(Error::InvalidArgument(format!("Negative amount of rain in profile: {}", s))),
+            RainProfile::Gaussian { sigma, .. } if *sigma <= 0.0 => return Err(Error::InvalidArgument(format!("SIGMA of rain profile must be positive: {}", s))),
+            _ => {},
+        }
+        Ok(profile)
+    }
+}
+
+/// Rain during one step of schedule.
+#[derive(Debug, Clone, PartialEq)]
+pub enum StepRain {
+    /// Intensity - multiplier of amount of rain given by rain profile.
+    Scale(f64),
+    /// Amount of rain given separately for every point (by point index).
+    PerPoint(Vec<f64>),
+}
+
+/// Rain changing in time (hyetograph) - rain for consecutive simulation steps.
+/// Steps after the end of schedule are dry (water is only flowing).
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct RainSchedule {
+    steps: Vec<StepRain>,
+}
+
+impl RainSchedule {
+    /// Creates schedule from rain of consecutive steps.
+    pub fn new(steps: Vec<StepRain>) -> Self {
+        RainSchedule { steps }
+    }
+
+    /// Reads schedule: one step in one line, containing either one value (intensity)
+    /// or amount of rain for every point (values separated by spaces or commas).
+    /// Reading is finished on end-of-file or empty line.
+    pub fn read(mut reader: impl BufRead) -> Result<Self> {
+        let mut steps = Vec::new();
+
+        let mut buf = String::new();
+        loop {
+            match reader.read_line(&mut buf) {
+                Ok(n) if n > 1 => {
+                    let mut values = Vec::new();
+                    for v in buf.split(|c: char| c == ',' || c.is_whitespace()).filter(|v| !v.is_empty()) {
+                        match v.parse::<f64>() {
+                            Ok(v) => {
+                                if v < 0.0 || !v.is_finite() { return Err(Error::InvalidValue { input: Input::Schedule, line: steps.len() + 1, what: "invalid amount of rain", value: v }); }
+                                values.push(v);
+                            },
+                            Err(e) => return Err(Error::parse(Input::Schedule, steps.len() + 1, e)),
+                        }
+                    }
+                    steps.push(if values.len() == 1 { StepRain::Scale(values[0]) } else { StepRain::PerPoint(values) });
+                },
+                Ok(_) => break,
+                Err(e) => return Err(Error::parse(Input::Schedule, steps.len() + 1, e)),
+            }
+            buf.clear();
+        }
+        Ok(RainSchedule { steps })
+    }
+
+    /// Returns number of steps in schedule.
+    pub fn len(&self) -> usize {
+        self.steps.len()
+    }
+
+    /// Returns `true` if schedule has no steps.
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+
+    /// Checks that every step of schedule given per point has amount of rain for all `len` points.
+    pub fn check(&self, len: usize) -> Result<()> {
+        for (n, step) in self.steps.iter().enumerate() {
+            if let StepRain::PerPoint(values) = step {
+                if values.len() != len {
+                    return Err(Error::SizeMismatch { what: format!("Schedule step number {}", n + 1), len: values.len(), expected: len });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns amount of rain falling onto point at `idx` index in `step` (counted from 0).
+    /// `profile` - amount of rain for every point (see `RainProfile::distribution()`) scaled by step intensity.
+    pub fn amount(&self, step: usize, idx: usize, profile: &[f64]) -> f64 {
+        match self.steps.get(step) {
+            Some(StepRain::Scale(intensity)) => intensity * profile[idx],
+            Some(StepRain::PerPoint(values)) => values[idx],
+            None => 0.0,
+        }
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////////////////////////
+// Tests
+//
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::topology::{Grid2D, Line1D, Neighborhood};
+
+    #[test]
+    fn parse() {
+        assert_eq!("uniform:2".parse::<RainProfile>().unwrap(), RainProfile::Uniform(2.0));
+        assert_eq!("gradient:0:1".parse::<RainProfile>().unwrap(), RainProfile::Gradient { from: 0.0, to: 1.0, axis: 0 });
+        assert_eq!("gaussian:2:1.5:1,2".parse::<RainProfile>().unwrap(),
+            RainProfile::Gaussian { peak: 2.0, sigma: 1.5, centre: vec![1.0, 2.0] });
+        assert!("gradient:0".parse::<RainProfile>().is_err());
+        assert!("uniform:-1".parse::<RainProfile>().is_err());
+        assert!("gaussian:1:0:0".parse::<RainProfile>().is_err());
+        assert!("uniform:1:2".parse::<RainProfile>().is_err());
+        assert!("storm".parse::<RainProfile>().is_err());
+    }
+
+    #[test]
+    fn read() {
+        assert_eq!(RainProfile::read("1\n0.5\n2\n".as_bytes()).unwrap(), RainProfile::PerPoint(vec![1.0, 0.5, 2.0]));
+        assert!(RainProfile::read("1\n-1\n".as_bytes()).is_err());
+        assert!(RainProfile::read("1\nx\n".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn distribution() {
+        let line = Line1D::new(3);
+        assert_eq!(RainProfile::Uniform(2.0).distribution(&line).unwrap(), vec![2.0, 2.0, 2.0]);
+        assert!(RainProfile::PerPoint(vec![1.0, 2.0]).distribution(&line).is_err());
+        assert_eq!(RainProfile::Gradient { from: 0.0, to: 1.0, axis: 0 }.distribution(&line).unwrap(), vec![0.0, 0.5, 1.0]);
+        assert!(RainProfile::Gradient { from: 0.0, to: 1.0, axis: 1 }.distribution(&line).is_err());
+
+        let grid = Grid2D::new(2, 3, Neighborhood::VonNeumann);
+        assert_eq!(RainProfile::Gradient { from: 1.0, to: 3.0, axis: 1 }.distribution(&grid).unwrap(),
+            vec![1.0, 2.0, 3.0, 1.0, 2.0, 3.0]);
+        let storm = RainProfile::Gaussian { peak: 2.0, sigma: 1.0, centre: vec![0.0, 1.0] }.distribution(&grid).unwrap();
+        assert_eq!(storm[1], 2.0);
+        assert_eq!(storm[0], storm[2]);
+        assert_eq!(storm[0], storm[4]);
+        assert!(storm[5] < storm[4]);
+        assert!(RainProfile::Gaussian { peak: 2.0, sigma: 1.0, centre: vec![0.0] }.distribution(&grid).is_err());
+    }
+
+    #[test]
+    fn schedule() {
+        let schedule = RainSchedule::read("0.5\n0\n1, 2 3\n".as_bytes()).unwrap();
+        assert_eq!(schedule, RainSchedule::new(vec![
+            StepRain::Scale(0.5), StepRain::Scale(0.0), StepRain::PerPoint(vec![1.0, 2.0, 3.0])]));
+        assert!(schedule.check(3).is_ok());
+        assert!(schedule.check(2).is_err());
+        let profile = [2.0, 4.0, 6.0];
+        assert_eq!(schedule.amount(0, 1, &profile), 2.0);
+        assert_eq!(schedule.amount(1, 1, &profile), 0.0);
+        assert_eq!(schedule.amount(2, 1, &profile), 2.0);
+        assert_eq!(schedule.amount(3, 1, &profile), 0.0);
+        assert!(RainSchedule::read("1\n-1\n".as_bytes()).is_err());
+    }
+}
@@ -0,0 +1,604 @@
+/*!
+ * Registry of algorithms (solvers) available in the library.
+ * `AnySolver` allows to select algorithm at runtime (enum dispatch), as `Solver` trait is not object safe.
+ */
+
+use std::fmt;
+use std::io::{self, Write};
+use std::str::FromStr;
+
+use crate::error::{Error, Result};
+
+#[cfg(feature = "bigdecimal")]
+use bigdecimal::{BigDecimal, ToPrimitive};
+
+#[cfg(feature = "bigrational")]
+use crate::simul_manual_1th_br_v3::Rational;
+use crate::flow::FlowMode;
+use crate::losses::Losses;
+use crate::stats::Stats;
+use crate::topology::Topology;
+use crate::{simul_manual_1th_v1, simul_manual_1th_v2, simul_manual_1th_v3, simul_manual_1th_ev, simul_manual_1th_wl, simul_manual_1th_pq, simul_manual_mt_v2, Solver};
+#[cfg(feature = "bigdecimal")]
+use crate::simul_manual_1th_bd_v2;
+#[cfg(feature = "bigrational")]
+use crate::simul_manual_1th_br_v3;
+
+/// Identifies algorithm (module) used to solve problem.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SolverKind {
+    /// Module `simul_manual_1th_v1`.
+    V1,
+    /// Module `simul_manual_1th_v2` (default).
+    #[default]
+    V2,
+    /// Module `simul_manual_1th_bd_v2`.
+    #[cfg(feature = "bigdecimal")]
+    Bd,
+    /// Module `simul_manual_1th_v3`.
+    V3,
+    /// Module `simul_manual_1th_ev`.
+    Ev,
+    /// Module `simul_manual_1th_br_v3`.
+    #[cfg(feature = "bigrational")]
+    Br,
+    /// Module `simul_manual_mt_v2`.
+    Mt,
+    /// Module `simul_manual_1th_wl`.
+    Wl,
+    /// Module `simul_manual_1th_pq`.
+    Pq,
+}
+
+impl SolverKind {
+    /// All algorithms available in the library (depends on enabled cargo features).
+    pub const ALL: &'static [SolverKind] = &[
+        SolverKind::V1,
+        SolverKind::V2,
+        #[cfg(feature = "bigdecimal")]
+        SolverKind::Bd,
+        SolverKind::V3,
+        SolverKind::Ev,
+        #[cfg(feature = "bigrational")]
+        SolverKind::Br,
+        SolverKind::Mt,
+        SolverKind::Wl,
+        SolverKind::Pq,
+    ];
+
+    /// Returns short name of algorithm.
+    pub fn name(self) -> &'static str {
+        match self {
+            SolverKind::V1 => "v1",
+            SolverKind::V2 => "v2",
+            #[cfg(feature = "bigdecimal")]
+            SolverKind::Bd => "bd",
+            SolverKind::V3 => "v3",
+            SolverKind::Ev => "ev",
+            #[cfg(feature = "bigrational")]
+            SolverKind::Br => "br",
+            SolverKind::Mt => "mt",
+            SolverKind::Wl => "wl",
+            SolverKind::Pq => "pq",
+        }
+    }
+
+    /// Returns name of module which implements algorithm.
+    pub fn module(self) -> &'static str {
+        match self {
+            SolverKind::V1 => "simul_manual_1th_v1",
+            SolverKind::V2 => "simul_manual_1th_v2",
+            #[cfg(feature = "bigdecimal")]
+            SolverKind::Bd => "simul_manual_1th_bd_v2",
+            SolverKind::V3 => "simul_manual_1th_v3",
+            SolverKind::Ev => "simul_manual_1th_ev",
+            #[cfg(feature = "bigrational")]
+            SolverKind::Br => "simul_manual_1th_br_v3",
+            SolverKind::Mt => "simul_manual_mt_v2",
+            SolverKind::Wl => "simul_manual_1th_wl",
+            SolverKind::Pq => "simul_manual_1th_pq",
+        }
+    }
+
+    /// Returns short description of algorithm.
+    pub fn description(self) -> &'static str {
+        match self {
+            SolverKind::V1 => "iterative, points evaluated in entry data order (f64)",
+            SolverKind::V2 => "iterative, points evaluated from highest ground to lowest (f64)",
+            #[cfg(feature = "bigdecimal")]
+            SolverKind::Bd => "iterative, points evaluated from highest ground to lowest (BigDecimal)",
+            SolverKind::V3 => "exact, pool merging (f64)",
+            SolverKind::Ev => "exact, event driven in continuous time (f64)",
+            #[cfg(feature = "bigrational")]
+            SolverKind::Br => "exact, pool merging (BigRational)",
+            SolverKind::Mt => "iterative as v2, landscape divided between threads at ridge points (f64)",
+            SolverKind::Wl => "iterative as v2, only points touched by recent flows are revisited (f64)",
+            SolverKind::Pq => "iterative, points evaluated from highest water level to lowest, flows applied immediately, approximate (f64)",
+        }
+    }
+}
+
+impl fmt::Display for SolverKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for SolverKind {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match SolverKind::ALL.iter().find(|k| k.name() == s) {
+            Some(kind) => Ok(*kind),
+            None => {
+                let names = SolverKind::ALL.iter().map(|k| k.name()).collect::<Vec<_>>();
+                Err(Error::InvalidArgument(format!("Unknown solver: {} (expected one of: {})", s, names.join(", "))))
+            }
+        }
+    }
+}
+
+/// Solver of any kind, for landscape with topology `T`.
+pub enum AnySolver<T: Topology> {
+    V1(simul_manual_1th_v1::Landscape<T>),
+    V2(simul_manual_1th_v2::Landscape<T>),
+    #[cfg(feature = "bigdecimal")]
+    Bd(simul_manual_1th_bd_v2::Landscape<T>),
+    V3(simul_manual_1th_v3::Landscape<T>),
+    Ev(simul_manual_1th_ev::Landscape<T>),
+    #[cfg(feature = "bigrational")]
+    Br(simul_manual_1th_br_v3::Landscape<T>),
+    Mt(simul_manual_mt_v2::Landscape<T>),
+    Wl(simul_manual_1th_wl::Landscape<T>),
+    Pq(simul_manual_1th_pq::Landscape<T>),
+}
+
+/// Calls the same expression for solver of any kind.
+macro_rules! dispatch {
+    ($solver:expr, $s:ident => $body:expr) => {
+        match $solver {
+            AnySolver::V1($s) => $body,
+            AnySolver::V2($s) => $body,
+            #[cfg(feature = "bigdecimal")]
+            AnySolver::Bd($s) => $body,
+            AnySolver::V3($s) => $body,
+            AnySolver::Ev($s) => $body,
+            #[cfg(feature = "bigrational")]
+            AnySolver::Br($s) => $body,
+            AnySolver::Mt($s) => $body,
+            AnySolver::Wl($s) => $body,
+            AnySolver::Pq($s) => $body,
+        }
+    };
+}
+
+impl<T: Topology> AnySolver<T> {
+    /// Creates solver of given `kind` with default precision.
+    /// `ph` must contain finite height of every point of topology, otherwise error is returned.
+    pub fn create(kind: SolverKind, ph: Vec<f64>, topology: T) -> Result<Self> {
+        Ok(match kind {
+            SolverKind::V1 => AnySolver::V1(simul_manual_1th_v1::Landscape::create_with_topology(ph, topology)?),
+            SolverKind::V2 => AnySolver::V2(simul_manual_1th_v2::Landscape::create_with_topology(ph, topology)?),
+            #[cfg(feature = "bigdecimal")]
+            SolverKind::Bd => AnySolver::Bd(simul_manual_1th_bd_v2::Landscape::create_with_topology(ph, topology)?),
+            SolverKind::V3 => AnySolver::V3(simul_manual_1th_v3::Landscape::create_with_topology(ph, topology)?),
+            SolverKind::Ev => AnySolver::Ev(simul_manual_1th_ev::Landscape::create_with_topology(ph, topology)?),
+            #[cfg(feature = "bigrational")]
+            SolverKind::Br => AnySolver::Br(simul_manual_1th_br_v3::Landscape::create_with_topology(ph, topology)?),
+            SolverKind::Mt => AnySolver::Mt(simul_manual_mt_v2::Landscape::create_with_topology(ph, topology)?),
+            SolverKind::Wl => AnySolver::Wl(simul_manual_1th_wl::Landscape::create_with_topology(ph, topology)?),
+            SolverKind::Pq => AnySolver::Pq(simul_manual_1th_pq::Landscape::create_with_topology(ph, topology)?),
+        })
+    }
+
+    /// Creates solver of given `kind`.
+    /// `precision` precision in which to perform simulation (ignored by exact algorithms).
+    pub fn create_with_precision(kind: SolverKind, ph: Vec<f64>, topology: T, precision: f64) -> Result<Self> {
+        Ok(match kind {
+            SolverKind::V1 => AnySolver::V1(simul_manual_1th_v1::Landscape::create_with_topology_and_precision(ph, topology, precision)?),
+            SolverKind::V2 => AnySolver::V2(simul_manual_1th_v2::Landscape::create_with_topology_and_precision(ph, topology, precision)?),
+            #[cfg(feature = "bigdecimal")]
+            SolverKind::Bd => AnySolver::Bd(simul_manual_1th_bd_v2::Landscape::create_with_topology_and_precision(ph, topology, precision.into())?),
+            SolverKind::V3 => AnySolver::V3(simul_manual_1th_v3::Landscape::create_with_topology_and_precision(ph, topology, precision)?),
+            SolverKind::Ev => AnySolver::Ev(simul_manual_1th_ev::Landscape::create_with_topology_and_precision(ph, topology, precision)?),
+            #[cfg(feature = "bigrational")]
+            SolverKind::Br => AnySolver::Br(simul_manual_1th_br_v3::Landscape::create_with_topology_and_precision(ph, topology, Rational::try_from_f64(precision)?)?),
+            SolverKind::Mt => AnySolver::Mt(simul_manual_mt_v2::Landscape::create_with_topology_and_precision(ph, topology, precision)?),
+            SolverKind::Wl => AnySolver::Wl(simul_manual_1th_wl::Landscape::create_with_topology_and_precision(ph, topology, precision)?),
+            SolverKind::Pq => AnySolver::Pq(simul_manual_1th_pq::Landscape::create_with_topology_and_precision(ph, topology, precision)?),
+        })
+    }
+
+    /// Returns kind of solver.
+    pub fn kind(&self) -> SolverKind {
+        match self {
+            AnySolver::V1(_) => SolverKind::V1,
+            AnySolver::V2(_) => SolverKind::V2,
+            #[cfg(feature = "bigdecimal")]
+            AnySolver::Bd(_) => SolverKind::Bd,
+            AnySolver::V3(_) => SolverKind::V3,
+            AnySolver::Ev(_) => SolverKind::Ev,
+            #[cfg(feature = "bigrational")]
+            AnySolver::Br(_) => SolverKind::Br,
+            AnySolver::Mt(_) => SolverKind::Mt,
+            AnySolver::Wl(_) => SolverKind::Wl,
+            AnySolver::Pq(_) => SolverKind::Pq,
+        }
+    }
+
+    /// Marks point at `idx` index as drain.
+    pub fn set_drain(&mut self, idx: usize) {
+        dispatch!(self, s => s.set_drain(idx))
+    }
+
+    /// Simulates `dt` hours of falling rain - see `Solver::rain_for()`.
+    /// Rain intensity is converted from `f64` to type used by solver.
+    pub fn rain_for(&mut self, dt: f64, rain_distr: impl Fn(usize) -> f64 + Sync, return_result: bool) -> Result<Levels<'_>> {
+        Ok(match self {
+            AnySolver::V1(s) => Levels::F64(s.rain_for(dt, rain_distr, return_result)?),
+            AnySolver::V2(s) => Levels::F64(s.rain_for(dt, rain_distr, return_result)?),
+            #[cfg(feature = "bigdecimal")]
+            AnySolver::Bd(s) => Levels::Bd(s.rain_for(dt, |idx| rain_distr(idx).into(), return_result)?),
+            AnySolver::V3(s) => Levels::F64(s.rain_for(dt, rain_distr, return_result)?),
+            AnySolver::Ev(s) => Levels::F64(s.rain_for(dt, rain_distr, return_result)?),
+            #[cfg(feature = "bigrational")]
+            AnySolver::Br(s) => Levels::Br(s.rain_for(dt, |idx| rain_distr(idx).into(), return_result)?),
+            AnySolver::Mt(s) => Levels::F64(s.rain_for(dt, rain_distr, return_result)?),
+            AnySolver::Wl(s) => Levels::F64(s.rain_for(dt, rain_distr, return_result)?),
+            AnySolver::Pq(s) => Levels::F64(s.rain_for(dt, rain_distr, return_result)?),
+        })
+    }
+
+    /// Simulates uniform rain until time `t` and returns water levels - see `Solver::levels_at()`.
+    pub fn levels_at(&mut self, t: f64, density: f64) -> Result<Levels<'_>> {
+        Ok(match self {
+            AnySolver::V1(s) => Levels::F64(s.levels_at(t, density)?),
+            AnySolver::V2(s) => Levels::F64(s.levels_at(t, density)?),
+            #[cfg(feature = "bigdecimal")]
+            AnySolver::Bd(s) => Levels::Bd(s.levels_at(t, density.into())?),
+            AnySolver::V3(s) => Levels::F64(s.levels_at(t, density)?),
+            AnySolver::Ev(s) => Levels::F64(s.levels_at(t, density)?),
+            #[cfg(feature = "bigrational")]
+            AnySolver::Br(s) => Levels::Br(s.levels_at(t, Rational::try_from_f64(density)?)?),
+            AnySolver::Mt(s) => Levels::F64(s.levels_at(t, density)?),
+            AnySolver::Wl(s) => Levels::F64(s.levels_at(t, density)?),
+            AnySolver::Pq(s) => Levels::F64(s.levels_at(t, density)?),
+        })
+    }
+
+    /// Simulates one step of falling rain - see `Solver::rain()`.
+    /// Rain density is converted from `f64` to type used by solver.
+    pub fn rain(&mut self, rain_distr: impl Fn(usize) -> f64 + Sync, return_result: bool) -> Result<Levels<'_>> {
+        self.rain_for(1.0, rain_distr, return_result)
+    }
+
+    /// Simulates one step of rain uniform thru entire landscape - see `Solver::rain_uniform()`.
+    pub fn rain_uniform(&mut self, cnt: f64, return_result: bool) -> Result<Levels<'_>> {
+        self.rain(|_| cnt, return_result)
+    }
+
+    /// Returns simulation precision (converted to `f64`).
+    pub fn precision(&self) -> f64 {
+        match self {
+            #[cfg(feature = "bigdecimal")]
+            AnySolver::Bd(s) => s.precision().to_f64().unwrap_or(f64::NAN),
+            #[cfg(feature = "bigrational")]
+            AnySolver::Br(s) => s.precision().to_f64(),
+            AnySolver::V1(s) => s.precision(),
+            AnySolver::V2(s) => s.precision(),
+            AnySolver::V3(s) => s.precision(),
+            AnySolver::Ev(s) => s.precision(),
+            AnySolver::Mt(s) => s.precision(),
+            AnySolver::Wl(s) => s.precision(),
+            AnySolver::Pq(s) => s.precision(),
+        }
+    }
+
+    /// Returns amount of water which left landscape during last `rain()` call (converted to `f64`).
+    pub fn outflow(&self) -> f64 {
+        match self {
+            #[cfg(feature = "bigdecimal")]
+            AnySolver::Bd(s) => s.outflow().to_f64().unwrap_or(f64::NAN),
+            #[cfg(feature = "bigrational")]
+            AnySolver::Br(s) => s.outflow().to_f64(),
+            AnySolver::V1(s) => s.outflow(),
+            AnySolver::V2(s) => s.outflow(),
+            AnySolver::V3(s) => s.outflow(),
+            AnySolver::Ev(s) => s.outflow(),
+            AnySolver::Mt(s) => s.outflow(),
+            AnySolver::Wl(s) => s.outflow(),
+            AnySolver::Pq(s) => s.outflow(),
+        }
+    }
+
+    /// Returns amount of rain added during last `rain()` call (converted to `f64`).
+    pub fn rain_added(&self) -> f64 {
+        match self {
+            #[cfg(feature = "bigdecimal")]
+            AnySolver::Bd(s) => s.rain_added().to_f64().unwrap_or(f64::NAN),
+            #[cfg(feature = "bigrational")]
+            AnySolver::Br(s) => s.rain_added().to_f64(),
+            AnySolver::V1(s) => s.rain_added(),
+            AnySolver::V2(s) => s.rain_added(),
+            AnySolver::V3(s) => s.rain_added(),
+            AnySolver::Ev(s) => s.rain_added(),
+            AnySolver::Mt(s) => s.rain_added(),
+            AnySolver::Wl(s) => s.rain_added(),
+            AnySolver::Pq(s) => s.rain_added(),
+        }
+    }
+
+    /// Returns amount of water stored in landscape (converted to `f64`).
+    pub fn stored(&self) -> f64 {
+        match self {
+            #[cfg(feature = "bigdecimal")]
+            AnySolver::Bd(s) => s.stored().to_f64().unwrap_or(f64::NAN),
+            #[cfg(feature = "bigrational")]
+            AnySolver::Br(s) => s.stored().to_f64(),
+            AnySolver::V1(s) => s.stored(),
+            AnySolver::V2(s) => s.stored(),
+            AnySolver::V3(s) => s.stored(),
+            AnySolver::Ev(s) => s.stored(),
+            AnySolver::Mt(s) => s.stored(),
+            AnySolver::Wl(s) => s.stored(),
+            AnySolver::Pq(s) => s.stored(),
+        }
+    }
+
+    /// Returns statistics of water flow simulation during last `rain()` call.
+    pub fn stats(&self) -> Stats {
+        dispatch!(self, s => s.stats())
+    }
+
+    /// Returns simulated time (in hours).
+    pub fn time(&self) -> f64 {
+        dispatch!(self, s => s.time())
+    }
+
+    /// Sets surface area of every point - see `Solver::set_areas()`.
+    pub fn set_areas(&mut self, areas: Vec<f64>) -> Result<()> {
+        dispatch!(self, s => s.set_areas(areas))
+    }
+
+    /// Enables verification mode - see `Solver::set_verify()`.
+    pub fn set_verify(&mut self, verify: bool) -> Result<()> {
+        dispatch!(self, s => s.set_verify(verify))
+    }
+
+    /// Sets flow rate law - see `Solver::set_flow_mode()`.
+    pub fn set_flow_mode(&mut self, mode: FlowMode) -> Result<()> {
+        dispatch!(self, s => s.set_flow_mode(mode))
+    }
+
+    /// Enables water losses - see `Solver::set_losses()`.
+    pub fn set_losses(&mut self, losses: Losses) -> Result<()> {
+        dispatch!(self, s => s.set_losses(losses))
+    }
+
+    /// Returns amount of water lost during last `rain()` call (converted to `f64`).
+    pub fn loss(&self) -> f64 {
+        match self {
+            #[cfg(feature = "bigdecimal")]
+            AnySolver::Bd(s) => s.loss().to_f64().unwrap_or(f64::NAN),
+            #[cfg(feature = "bigrational")]
+            AnySolver::Br(s) => s.loss().to_f64(),
+            AnySolver::V1(s) => s.loss(),
+            AnySolver::V2(s) => s.loss(),
+            AnySolver::V3(s) => s.loss(),
+            AnySolver::Ev(s) => s.loss(),
+            AnySolver::Mt(s) => s.loss(),
+            AnySolver::Wl(s) => s.loss(),
+            AnySolver::Pq(s) => s.loss(),
+        }
+    }
+}
+
+/// Water levels returned by solver of any kind.
+#[derive(Debug, Clone, Copy)]
+pub enum Levels<'a> {
+    F64(&'a [f64]),
+    #[cfg(feature = "bigdecimal")]
+    Bd(&'a [BigDecimal]),
+    #[cfg(feature = "bigrational")]
+    Br(&'a [Rational]),
+}
+
+impl Levels<'_> {
+    /// Returns number of points.
+    pub fn len(&self) -> usize {
+        match self {
+            Levels::F64(l) => l.len(),
+            #[cfg(feature = "bigdecimal")]
+            Levels::Bd(l) => l.len(),
+            #[cfg(feature = "bigrational")]
+            Levels::Br(l) => l.len(),
+        }
+    }
+
+    /// Returns `true` if there are no levels (e.g. result was not requested).
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Writes levels as comma separated values, `cols` values in one line.
+    pub fn write_rows(&self, out: &mut impl Write, cols: usize) -> io::Result<()> {
+        match self {
+            Levels::F64(l) => write_rows(l, out, cols),
+            #[cfg(feature = "bigdecimal")]
+            Levels::Bd(l) => write_rows(l, out, cols),
+            #[cfg(feature = "bigrational")]
+            Levels::Br(l) => write_rows(l, out, cols),
+        }
+    }
+}
+
+/// Number of values formatted in parallel before they are written (smaller landscapes are written sequentially).
+#[cfg(feature = "parallel")]
+const PARALLEL_BLOCK: usize = 1 << 16;
+
+/// Writes `levels` as comma separated values, `cols` values in one line.
+#[cfg(not(feature = "parallel"))]
+fn write_rows<H: fmt::Display + Sync>(levels: &[H], out: &mut impl Write, cols: usize) -> io::Result<()> {
+    write_rows_sequential(levels, out, cols)
+}
+
+/// Writes `levels` as comma separated values, `cols` values in one line.
+/// Rows are formatted in parallel in blocks of about `PARALLEL_BLOCK` values and written in order.
+#[cfg(feature = "parallel")]
+fn write_rows<H: fmt::Display + Sync>(levels: &[H], out: &mut impl Write, cols: usize) -> io::Result<()> {
+    use rayon::prelude::*;
+    if levels.len() <= PARALLEL_BLOCK {
+        return write_rows_sequential(levels, out, cols);
+    }
+    let cols = cols.max(1);
+    let block_rows = (PARALLEL_BLOCK / cols).max(1);
+    for block in levels.chunks(cols * block_rows) {
+        let text = block.par_chunks(cols).map(|row| {
+            let mut buf = Vec::new();
+            write_row(row, &mut buf).map(|_| buf)
+        }).collect::<io::Result<Vec<_>>>()?;
+        for buf in text {
+            out.write_all(&buf)?;
+        }
+    }
+    Ok(())
+}
+
+/// Writes `levels` as comma separated values, `cols` values in one line, row by row.
+fn write_rows_sequential<H: fmt::Display>(levels: &[H], out: &mut impl Write, cols: usize) -> io::Result<()> {
+    for row in levels.chunks(cols.max(1)) {
+        write_row(row, out)?;
+    }
+    Ok(())
+}
+
+/// Writes one row of levels followed by new line.
+fn write_row<H: fmt::Display>(row: &[H], out: &mut impl Write) -> io::Result<()> {
+    for (i, h) in row.iter().enumerate() {
+        if i > 0 {
+            out.write_all(b", ")?;
+        }
+        write!(out, "{}", h)?;
+    }
+    out.write_all(b"\n")
+}
+
+///////////////////////////////////////////////////////////////////////////////////////////////////
+// Tests
+//
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names() {
+        for kind in SolverKind::ALL {
+            assert_eq!(kind.name().parse::<SolverKind>().unwrap(), *kind);
+        }
+        assert_eq!(SolverKind::default(), SolverKind::V2);
+        assert!("v0".parse::<SolverKind>().is_err());
+    }
+
+    #[test]
+    fn any_solver() {
+        use crate::topology::Line1D;
+        for kind in SolverKind::ALL {
+            let mut solver = AnySolver::create(*kind, vec![3.0, 1.0, 6.0, 4.0, 8.0, 9.0], Line1D::new(6)).unwrap();
+            assert_eq!(solver.kind(), *kind);
+            let mut out = Vec::new();
+            solver.rain_uniform(1.0, true).unwrap().write_rows(&mut out, 3).unwrap();
+            if solver.precision() == 0.0 {
+                assert_eq!(String::from_utf8(out).unwrap(), "4, 4, 6\n6, 8, 9\n");
+            }
+            assert!(solver.rain_uniform(1.0, false).unwrap().is_empty());
+            let limited = solver.set_flow_mode(FlowMode::Linear(1.0)).is_ok();
+            assert_eq!(limited, matches!(kind, SolverKind::V1 | SolverKind::V2 | SolverKind::Mt | SolverKind::Wl));
+        }
+    }
+
+    #[test]
+    fn levels_at() {
+        use crate::topology::Line1D;
+        for kind in SolverKind::ALL {
+            let mut solver = AnySolver::create(*kind, vec![8.0, 8.0, 1.0], Line1D::new(3)).unwrap();
+            for t in &[f64::NAN, f64::INFINITY, f64::NEG_INFINITY] {
+                assert!(matches!(solver.levels_at(*t, 1.0), Err(Error::InvalidArgument(_))), "{}: {}", kind, t);
+            }
+            assert!(solver.levels_at(1.5, 1.0).is_ok(), "{}", kind);
+            assert!(matches!(solver.levels_at(1.0, 1.0), Err(Error::TimeBeforeCurrent { .. })), "{}", kind);
+        }
+    }
+
+    #[test]
+    fn grid_pit() {
+        use crate::topology::{Grid2D, Neighborhood};
+        // All points around the pit flow into it at once - solvers must not overshoot and oscillate forever.
+        let points = vec![3.0, 3.0, 3.0, 3.0, 0.0, 3.0, 3.0, 3.0, 3.0];
+        for neighborhood in &[Neighborhood::VonNeumann, Neighborhood::Moore] {
+            let levels = |kind| {
+                let mut solver = AnySolver::create(kind, points.clone(), Grid2D::new(3, 3, *neighborhood)).unwrap();
+                let mut out = Vec::new();
+                solver.rain_uniform(1.0, true).unwrap().write_rows(&mut out, 9).unwrap();
+                // Rational levels are printed as fractions.
+                let parse = |h: &str| match h.split_once('/') {
+                    Some((numer, denom)) => numer.parse::<f64>().unwrap() / denom.parse::<f64>().unwrap(),
+                    None => h.parse::<f64>().unwrap(),
+                };
+                String::from_utf8(out).unwrap().trim().split(", ").map(parse).collect::<Vec<_>>()
+            };
+            let expected = levels(SolverKind::V3);
+            assert!(expected.iter().all(|h| (h - 11.0 / 3.0).abs() < 1e-9));
+            for kind in SolverKind::ALL {
+                let result = levels(*kind);
+                assert!(result.iter().zip(&expected).all(|(h, e)| (h - e).abs() < 0.01), "{}: {:?}", kind, result);
+            }
+        }
+    }
+
+    #[test]
+    fn rows() {
+        // Enough values for several blocks of parallel formatting.
+        let levels: Vec<f64> = (0..200_000).map(|i| i as f64 / 4.0).collect();
+        let mut out = Vec::new();
+        Levels::F64(&levels).write_rows(&mut out, 7).unwrap();
+        let expected: String = levels.chunks(7)
+            .map(|row| row.iter().map(|h| h.to_string()).collect::<Vec<_>>().join(", ") + "\n")
+            .collect();
+        assert_eq!(String::from_utf8(out).unwrap(), expected);
+    }
+
+    #[test]
+    #[cfg(feature = "parallel")]
+    fn parallel_rows() {
+        // Parallel formatting must give exactly the same bytes as sequential one - for short rows, rows longer than block
+        // and landscapes not divisible into whole blocks.
+        let levels: Vec<f64> = (0..3 * PARALLEL_BLOCK + 5).map(|i| (i as f64).sqrt() - 100.0).collect();
+        for cols in &[1, 7, 1000, PARALLEL_BLOCK + 3, levels.len()] {
+            let (mut parallel, mut sequential) = (Vec::new(), Vec::new());
+            write_rows(&levels, &mut parallel, *cols).unwrap();
+            write_rows_sequential(&levels, &mut sequential, *cols).unwrap();
+            assert!(parallel == sequential, "output differs for {} columns", cols);
+        }
+    }
+
+    #[test]
+    fn negative_heights() {
+        use crate::topology::Line1D;
+        let points = vec![-7.0, -9.0, -4.0, -6.0, -2.0, -1.0];
+        assert!(crate::check_heights(&points).is_ok());
+        assert_eq!(crate::check_heights(&[1.0, f64::INFINITY]), Err(Error::NonFiniteHeight { point: 1, value: f64::INFINITY }));
+        for kind in SolverKind::ALL {
+            assert!(matches!(AnySolver::create(*kind, vec![1.0, f64::INFINITY], Line1D::new(2)), Err(Error::NonFiniteHeight { point: 1, .. })), "{}", kind);
+        }
+        for kind in SolverKind::ALL {
+            let mut solver = AnySolver::create(*kind, points.clone(), Line1D::new(6)).unwrap();
+            let mut out = Vec::new();
+            solver.rain_uniform(1.0, true).unwrap().write_rows(&mut out, 3).unwrap();
+            if solver.precision() == 0.0 {
+                assert_eq!(String::from_utf8(out).unwrap(), "-6, -6, -4\n-4, -2, -1\n", "{}", kind);
+            }
+            assert!((solver.stored() - 6.0).abs() < 1e-9, "{}", kind);
+        }
+    }
+}
@@ -4,77 +4,136 @@
  */
 
 use std::iter::FromIterator;
+use std::time::Instant;
 
-use anyhow::Result;
+use crate::error::Result;
+use crate::check_landscape;
 
-use bigdecimal::{BigDecimal, Zero};
+use crate::stats::Stats;
+use crate::topology::{Boundary, Line1D, Topology};
+use crate::verify::{self, Flow, Report, Violation};
+
+use bigdecimal::{BigDecimal, ToPrimitive, Zero};
 
 // use crate::PointHeight;
 /// Base unclehood type used for calculations during simulation in this module.
 type PointHeight = <Landscape as crate::Solver>::PointHeight;
 
 /// Represents entire 'world' where water is raining onto and flowing down from point (section in the paper) to point.
-pub struct Landscape {
+pub struct Landscape<T: Topology = Line1D> {
+    topology: T,
     points: Vec<Point>,
     points_idx: Vec<usize>,
     results: Vec<PointHeight>,
     precision: PointHeight,
+    /// Conditions at the edge of landscape (or drains) for every point.
+    outlets: Vec<Boundary<PointHeight>>,
+    /// Amount of rain added to landscape during last `rain()` call.
+    rain_added: PointHeight,
+    /// Amount of water which left landscape during last `rain()` call.
+    outflow: PointHeight,
+    /// Simulated time (in hours) since landscape was created.
+    time: f64,
+    /// Statistics of last `stabilize_water()` call.
+    stats: Stats,
+    /// Checks invariants of simulation (see `verify` module).
+    verify: bool,
 }
 
+/// Used in place of neighbor index for water flowing outside of landscape.
+const OUTSIDE: usize = usize::MAX;
+
 #[derive(Debug)]
 struct WaterUpdate {
     from_idx: usize,
     to_idx: usize,
     water: PointHeight,
-
-    #[cfg(any(feature = "state_fun_f64", feature = "state_fun_bd"))]
-    from: Point,
-    #[cfg(any(feature = "state_fun_f64", feature = "state_fun_bd"))]
-    to: Point,
 }
 
 impl Landscape {
-    /// Create Landscape object.
+    /// Create Landscape object with points placed on the line (as in problem description).
     /// `points` object is intentionally consumed to free memory as soon as possible.
     /// In this case however it is moved and re-used as buffer for results.
     #[allow(dead_code)]
-    pub fn create(ph: Vec<f64>) -> Self {
+    pub fn create(ph: Vec<f64>) -> Result<Self> {
+        let topology = Line1D::new(ph.len());
+        Self::create_with_topology(ph, topology)
+    }
+
+    /// Create Landscape object with points placed on the line (as in problem description).
+    /// `points` object is intentionally consumed to free memory as soon as possible.
+    /// `precision` precision in which to perform simulation, the less the worse performance.
+    /// Warning: setting precision equal to zero may cause simulation hang.
+    #[allow(dead_code)]
+    pub fn create_with_precision(ph: Vec<f64>, precision: PointHeight) -> Result<Self> {
+        let topology = Line1D::new(ph.len());
+        Self::create_with_topology_and_precision(ph, topology, precision)
+    }
+}
+
+impl<T: Topology> Landscape<T> {
+    /// Create Landscape object with points connected according to given `topology`.
+    /// `ph` must contain finite (possibly negative) height of every point of topology,
+    /// otherwise `Error::SizeMismatch` or `Error::NonFiniteHeight` is returned.
+    #[allow(dead_code)]
+    pub fn create_with_topology(ph: Vec<f64>, topology: T) -> Result<Self> {
+        check_landscape(&ph, topology.len())?;
         let mut points = Vec::with_capacity(ph.len());
         for h in &ph {
             points.push(Point::with_height((*h).into()));
         }
-        let mut points_idx = Vec::from_iter((0..ph.len()).into_iter());
-        points_idx.sort_unstable_by(|i, j| ph[*j].partial_cmp(&ph[*i]).unwrap());
+        let mut points_idx = Vec::from_iter(0..ph.len());
+        points_idx.sort_unstable_by(|i, j| ph[*j].total_cmp(&ph[*i]));
+        let ph_len = ph.len();
         let results = Vec::from_iter(ph.into_iter().map(|h| h.into()));
-        Landscape { points, points_idx, results, precision:BigDecimal::from(0.01) }
+        let outlets = (0..ph_len).map(|i| topology.boundary(i).map(BigDecimal::from)).collect();
+        Ok(Landscape { topology, points, points_idx, results, precision:BigDecimal::from(0.01), outlets, rain_added:Zero::zero(), outflow:Zero::zero(), time:0.0, stats:Stats::default(), verify:false })
     }
 
-    /// Create Landscape object.
-    /// `points` object is intentionally consumed to free memory as soon as possible.
+    /// Create Landscape object with points connected according to given `topology`.
     /// `precision` precision in which to perform simulation, the less the worse performance.
     /// Warning: setting precision equal to zero may cause simulation hang.
     #[allow(dead_code)]
-    pub fn create_with_precision(ph: Vec<f64>, precision: PointHeight) -> Self {
-        let mut landscape = Self::create(ph);
+    pub fn create_with_topology_and_precision(ph: Vec<f64>, topology: T, precision: PointHeight) -> Result<Self> {
+        let mut landscape = Self::create_with_topology(ph, topology)?;
         landscape.precision = precision;
-        landscape
+        Ok(landscape)
+    }
+
+    /// Marks point at `idx` index as drain - water flows from it outside of landscape (as to infinitely low neighbor).
+    #[allow(dead_code)]
+    pub fn set_drain(&mut self, idx: usize) {
+        self.outlets[idx] = Boundary::Outflow;
     }
 
-    // TODO: This could be implemented as different specializations for different points passed as template parameter
     /// Determines directions in which water can flow from point at `idx` index.
-    fn neighbors(&self, idx: usize) -> impl Iterator<Item=usize> {
-        Iter1D {idx, max:self.points.len(), iter:0}
+    fn neighbors(&self, idx: usize) -> T::Neighbors<'_> {
+        self.topology.neighbors(idx)
+    }
+
+    /// Returns number of neighbors of point at `idx` index which send water to it (wet and higher by more than precision).
+    fn senders(&self, idx: usize) -> usize {
+        let h = self.points[idx].get_height();
+        self.neighbors(idx)
+            .filter(|ni| self.points[*ni].water > self.precision && self.points[*ni].get_height() > &h + &self.precision)
+            .count()
     }
 
     /// Function that determines how water is flowing thru landscape.
     /// Please look at `README.md` for more information.
     fn stabilize_water(&mut self) -> Result<()> {
-        #[cfg(any(feature = "state_fun_f64", feature = "state_fun_bd"))]
-        let (state_lbound, mut state) = (self.calc_state_lbound(), self.calc_state());
+        self.stats = Stats::default();
+        let start = Instant::now();
+
+        // State function and volume of water are calculated only in verification mode.
+        let (base, volume) = if self.verify { (self.lowest_ground(), self.calc_volume() + &self.outflow) } else { (0.0, Zero::zero()) };
+        let (state_lbound, mut state) = if self.verify { (self.calc_state_lbound(base), self.calc_state(base)) } else { (0.0, 0.0) };
 
         let mut send_water_to = Vec::new(); // TODO: possibly use smallvec or tiny_vec
         let mut water_update = Vec::new();
         loop {
+            self.stats.iterations += 1;
+            self.stats.visits += self.points.len();
             water_update.clear();
             for pi in &self.points_idx {
                 let pw = self.points[*pi].water.clone();
@@ -89,24 +148,41 @@ impl Landscape {
                         send_water_to.push(ni);
                     }
                 }
+                let flows_outside = match &self.outlets[*pi] {
+                    Boundary::Wall => false,
+                    Boundary::Outflow => true,
+                    Boundary::Reservoir(level) => ph > level.clone() + &self.precision,
+                };
+                if flows_outside {
+                    send_water_to.push(OUTSIDE);
+                }
                 if send_water_to.is_empty() {
                     continue;
                 }
                 let equal_fraction = pw / BigDecimal::from(send_water_to.len() as f64);
                 for ni in &send_water_to {
-                    let diff = self.points[*pi].get_height() - self.points[*ni].get_height();
-                    if diff > self.precision {
-                        let flow_amt = if equal_fraction < diff.half() { equal_fraction.clone() } else { diff.half() };
+                    let flow_amt = if *ni == OUTSIDE {
+                        match &self.outlets[*pi] {
+                            // Level of reservoir does not change, so water may flow until levels are equal.
+                            Boundary::Reservoir(level) => {
+                                let diff = self.points[*pi].get_height() - level;
+                                if diff > self.precision { Some(if equal_fraction < diff { equal_fraction.clone() } else { diff }) } else { None }
+                            },
+                            _ => Some(equal_fraction.clone()),
+                        }
+                    } else {
+                        // Inflow is shared by all points sending water to the neighbor, so that it does not overshoot.
+                        let diff = self.points[*pi].get_height() - self.points[*ni].get_height();
+                        let senders = self.senders(*ni);
+                        let equal_diff = if senders > 1 { &diff / BigDecimal::from((senders + 1) as f64) } else { diff.half() };
+                        if diff > self.precision { Some(if equal_fraction < equal_diff { equal_fraction.clone() } else { equal_diff }) } else { None }
+                    };
+                    if let Some(flow_amt) = flow_amt {
                         water_update.push(
                             WaterUpdate {
                                 from_idx: *pi,
                                 to_idx: *ni,
                                 water: flow_amt,
-                                
-                                #[cfg(any(feature = "state_fun_f64", feature = "state_fun_bd"))]
-                                from: self.points[*pi].clone(),
-                                #[cfg(any(feature = "state_fun_f64", feature = "state_fun_bd"))]
-                                to: self.points[*ni].clone(),
                             }
                         );
                     }
@@ -115,92 +191,126 @@ impl Landscape {
             if water_update.is_empty() {
                 break;
             }
+            self.stats.updates += water_update.len();
             for wu in &mut water_update {
+                self.stats.volume_moved += to_f64(&wu.water);
                 self.points[wu.from_idx].water -= wu.water.clone();
-                self.points[wu.to_idx].water += wu.water.clone();
-            }
-            
-            #[cfg(any(feature = "state_fun_f64", feature = "state_fun_bd"))] {
-            let new_state = self.calc_state();
-                if state < state_lbound {
-                    dbg!(&water_update);
-                    bail!("State function check failed: state ({}) < low bound ({})", state, state_lbound);
-                }
-                if new_state < state_lbound {
-                    dbg!(&water_update);
-                    bail!("State function check failed: new_state ({}) < low bound ({})", new_state, state_lbound);
-                }
-                if new_state > state {
-                    dbg!(&water_update);
-                    bail!("State function check failed: new_state ({}) > prev_state ({})", new_state, state);
+                if wu.to_idx == OUTSIDE {
+                    self.outflow += wu.water.clone();
+                } else {
+                    self.points[wu.to_idx].water += wu.water.clone();
                 }
-                if new_state == state {
-                    dbg!(&water_update);
-                    bail!("State function check failed: new_state ({}) == prev_state ({}); Function should return before", new_state, state);
-                }
-                // dbg!(&state_lbound, &new_state, &state);
-                state = new_state;
+            }
+
+            if self.verify {
+                state = self.check_iteration(self.stats.iterations, base, state, state_lbound, &water_update)?;
             }
         }
+        if self.verify {
+            self.check_volume(volume)?;
+        }
+        self.stats.duration = start.elapsed();
         Ok(())
     }
-    
-    #[cfg(feature = "state_fun_f64")]
-    fn calc_state(&self) -> f64 {
-        let mut state = 0.0;
-        for p in &self.points {
-            state += (p.get_height() as f64).powf(1.4);
+
+    /// Checks invariants after iteration of main loop (verification mode) and returns new value of state function.
+    fn check_iteration(&self, iteration: usize, base: f64, state: f64, state_lbound: f64, water_update: &[WaterUpdate]) -> Result<f64> {
+        let new_state = self.calc_state(base);
+        let mut report = Report::new(self.time, iteration);
+        if new_state < state_lbound {
+            report.violations.push(Violation::StateBelowBound { state: new_state, bound: state_lbound });
+        }
+        if new_state >= state {
+            report.violations.push(Violation::StateNotDecreasing { before: state, after: new_state });
+        }
+        for (point, p) in self.points.iter().enumerate() {
+            if p.water < -&self.precision {
+                report.violations.push(Violation::NegativeWater { point, water: to_f64(&p.water) });
+            }
+        }
+        if !report.is_ok() {
+            // Offending flows: from points with negative water and those after which receiving point is higher than sending one.
+            let negative = report.points();
+            report.flows = water_update.iter().filter(|wu| negative.contains(&wu.from_idx)
+                || (wu.to_idx != OUTSIDE && self.points[wu.to_idx].get_height() > self.points[wu.from_idx].get_height())
+            ).map(|wu| Flow {
+                from: wu.from_idx,
+                to: if wu.to_idx == OUTSIDE { None } else { Some(wu.to_idx) },
+                volume: to_f64(&wu.water),
+            }).collect();
         }
-        state        
+        report.into_result()?;
+        Ok(new_state)
     }
 
-    #[cfg(feature = "state_fun_f64")]
-    fn calc_state_lbound(&self) -> f64 {
-        let mut lbound = 0.0;
-        for p in &self.points {
-            lbound += (p.ground as f64).powf(1.4);
+    /// Checks that water stored in landscape plus outflow is equal to `expected` volume
+    /// and that no point has negative amount of water (verification mode).
+    /// Water is only moved from point to point, so calculations are exact and strict comparison is used.
+    fn check_volume(&self, expected: PointHeight) -> Result<()> {
+        let actual = self.calc_volume() + &self.outflow;
+        let mut report = Report::new(self.time, 0);
+        if actual != expected {
+            report.violations.push(Violation::MassNotConserved { expected: to_f64(&expected), actual: to_f64(&actual) });
         }
-        lbound        
+        for (point, p) in self.points.iter().enumerate() {
+            if p.water < -&self.precision {
+                report.violations.push(Violation::NegativeWater { point, water: to_f64(&p.water) });
+            }
+        }
+        report.into_result()
     }
 
-    /* Note: I created those versions while debugging failure on calc_state checks.
-       But it turned out to be caused by PointHeight being f32 instead of f64.
-       In practice this versions seems to be useless because of huge performance degradation in compare to f64.
-       But left them in place just in case.
-     */
-    #[cfg(all(feature = "state_fun_bd", not(feature = "state_fun_f64")))]
-    fn calc_state(&self) -> BigDecimal {
-        let mut state = Zero::zero();
-        for p in &self.points {
-            state += BigDecimal::from(p.get_height()).square();
-        }
-        state        
+    /// Returns the lowest ground level of landscape.
+    fn lowest_ground(&self) -> f64 {
+        self.points.iter().map(|p| to_f64(&p.ground)).fold(f64::INFINITY, f64::min)
     }
 
-    #[cfg(all(feature = "state_fun_bd", not(feature = "state_fun_f64")))]
-    fn calc_state_lbound(&self) -> BigDecimal {
-        let mut lbound = Zero::zero();
-        for p in &self.points {
-            lbound += BigDecimal::from(p.ground).square();
-        }
-        lbound        
+    /// Returns value of state function (it decreases with every flow of water).
+    /// Function is calculated on `f64` - it is precise enough, while `BigDecimal` would degrade performance hugely.
+    fn calc_state(&self, base: f64) -> f64 {
+        self.points.iter().map(|p| verify::state(to_f64(&p.get_height()), 1.0, base)).sum()
     }
+
+    /// Returns value of state function of dry landscape - state function can not be lower.
+    fn calc_state_lbound(&self, base: f64) -> f64 {
+        self.points.iter().map(|p| verify::state(to_f64(&p.ground), 1.0, base)).sum()
+    }
+
+    /// Returns total amount of water stored in landscape.
+    fn calc_volume(&self) -> PointHeight {
+        self.points.iter().map(|p| &p.water).sum()
+    }
+}
+
+/// Converts number to `f64` (used by verification reports and statistics).
+fn to_f64(v: &PointHeight) -> f64 {
+    v.to_f64().unwrap_or(f64::NAN)
 }
 // impl Copy for BigDecimal {
 
 // }
 
-impl crate::Solver for Landscape {
+impl<T: Topology> crate::Solver for Landscape<T> {
     /// Base unclehood type used for calculations during simulation in this module.
     type PointHeight = BigDecimal; 
     
-    /// Simulates one step of falling rain.
-    fn rain(&mut self, rain_distr: impl Fn(usize) -> PointHeight, return_result: bool) -> Result<&[PointHeight]> {
+    /// Simulates `dt` hours of falling rain.
+    fn rain_for(&mut self, dt: f64, rain_distr: impl Fn(usize) -> PointHeight + Sync, return_result: bool) -> Result<&[PointHeight]> {
+        self.rain_added = Zero::zero();
+        self.outflow = Zero::zero();
+        // Multiplication would increase scale of numbers (and so slow down simulation) even if `dt` is 1.
+        let dt_bd = if dt == 1.0 { None } else { Some(BigDecimal::from(dt)) };
         for (idx, p) in self.points.iter_mut().enumerate() {
-            p.rain(rain_distr(idx));
+            let rain = match &dt_bd {
+                Some(dt_bd) => rain_distr(idx) * dt_bd,
+                None => rain_distr(idx),
+            };
+            self.rain_added += &rain;
+            p.rain(rain);
         }
 
         self.stabilize_water()?;
+        self.time += dt;
 
         if return_result {
             for (i, p) in self.points.iter().enumerate() {
@@ -214,6 +324,29 @@ impl crate::Solver for Landscape {
     
     /// Returns simulation precision.
     fn precision(&self) -> PointHeight { self.precision.clone() }
+
+    /// Returns amount of water which left landscape during last `rain()` call.
+    fn outflow(&self) -> PointHeight { self.outflow.clone() }
+
+    /// Returns simulated time (in hours).
+    fn time(&self) -> f64 { self.time }
+
+    /// Returns statistics of water flow simulation during last `rain()` call.
+    fn stats(&self) -> Stats { self.stats }
+
+    /// Returns amount of rain added to landscape during last `rain()` call.
+    fn rain_added(&self) -> PointHeight { self.rain_added.clone() }
+
+    /// Returns amount of water stored in landscape.
+    fn stored(&self) -> PointHeight {
+        self.calc_volume()
+    }
+
+    /// Enables checking of invariants of simulation.
+    fn set_verify(&mut self, verify: bool) -> Result<()> {
+        self.verify = verify;
+        Ok(())
+    }
 }
 
 /// Represents point (section) on landscape
@@ -246,55 +379,9 @@ impl Point {
     }
 }
 
-struct Iter1D {
-    idx: usize,
-    max: usize,
-    iter: u8,
-}
-
-impl Iterator for Iter1D {
-    type Item = usize;
-    fn next(&mut self) -> Option<Self::Item> {
-        match self.iter {
-            0 => {
-                self.iter = 1;
-                if self.idx > 0 { Some(self.idx - 1) } else { if self.max > 1 { Some(1) } else { None }}
-            },
-            1 => {
-                self.iter = 2;
-                if self.idx == 0 { None } else { if self.idx < self.max - 1 { Some(self.idx + 1) } else { None }}
-            },
-            _ => None
-        }
-    }
-}
-
-/* 
-///Potentially to build more complicated topology
-#[derive(Debug, Clone)]
-struct PointFreehand {
-    ground: PointHeight,
-    neighbors: Vec<usize>,
-}
-
-impl PointFreehand {
-    fn with_height(h: PointHeight) -> Self {
-        PointFreehand { 
-            ground: h,
-            neighbors: Vec::with_capacity(2) 
-        }
-    }
-    
-    #[inline]
-    fn add_neighbor(&mut self, other: usize) {
-        self.neighbors.push(other);
-    }
-}
-*/
-
 ///////////////////////////////////////////////////////////////////////////////////////////////////
-/// Tests
-/// 
+// Tests
+//
 
 #[cfg(test)]
 mod tests {
@@ -310,10 +397,10 @@ mod tests {
             // let mut diff = Zero::zero();
             for (i, _r) in left.iter().enumerate() {
                 if (left[i].clone() - &right[i]).abs() > precision.clone() * BigDecimal::from(left.len() as f64) {
-                    assert!(false, "left[{}]={} != right[{}]={}", i, left[i], i, right[i]);
+                    panic!("left[{}]={} != right[{}]={}", i, left[i], i, right[i]);
                 }
                 // if (left[i] - right[i]).abs() > prec {
-                //     assert!(false, "left[{}]={} != right[{}]={}", i, left[i], i, right[i]);
+                //     panic!("left[{}]={} != right[{}]={}", i, left[i], i, right[i]);
                 // }
                 // diff += (left[i] - right[i]).abs();
                 // if diff > prec * (left.len() as PointHeight) {
@@ -326,7 +413,7 @@ mod tests {
     #[test]
     fn sv_case_sample() {
         let points = vec![3.0, 1.0, 6.0, 4.0, 8.0, 9.0];
-        let mut landscape = Landscape::create(points);
+        let mut landscape = Landscape::create(points).unwrap();
         let prec = landscape.precision();
         let result = landscape.rain_uniform(RAIN_DENSITY.into(), true).unwrap();
         compare(&prec, result, [4.0, 4.0, 6.0, 6.0, 8.0, 9.0]
@@ -337,7 +424,7 @@ mod tests {
     #[test]
     fn sv_case_mail2() {
         let points = vec![8.0, 8.0, 1.0];
-        let mut landscape = Landscape::create(points);
+        let mut landscape = Landscape::create(points).unwrap();
         let prec = landscape.precision();
         let result = landscape.rain_uniform(RAIN_DENSITY.into(), true).unwrap();
         compare(&prec, result, [8.0, 8.0, 4.0]
@@ -348,7 +435,7 @@ mod tests {
     #[test]
     fn sv_case_mail3() {
         let points = vec![1.0, 8.0, 8.0, 1.0];
-        let mut landscape = Landscape::create(points);
+        let mut landscape = Landscape::create(points).unwrap();
         let prec = landscape.precision();
         let result = landscape.rain_uniform(RAIN_DENSITY.into(), true).unwrap();
         compare(&prec, result, [3.0, 8.0, 8.0, 3.0]
@@ -359,7 +446,7 @@ mod tests {
     #[test]
     fn sv_case_mail4() {
         let points = vec![8.0, 4.0, 8.0, 8.0, 1.0];
-        let mut landscape = Landscape::create(points);
+        let mut landscape = Landscape::create(points).unwrap();
         let prec = landscape.precision();
         let result = landscape.rain_uniform(RAIN_DENSITY.into(), true).unwrap();
         compare(&prec, result, [8.0, 7.0, 8.0, 8.0, 3.0]
@@ -370,11 +457,24 @@ mod tests {
     #[test]
     fn sv_case_mail5() {
         let points = vec![1.0, 8.0, 8.0, 8.0, 1.0];
-        let mut landscape = Landscape::create(points);
+        let mut landscape = Landscape::create(points).unwrap();
         let prec = landscape.precision();
         let result = landscape.rain_uniform(RAIN_DENSITY.into(), true).unwrap();
         compare(&prec, result, [3.5, 8.0, 8.0, 8.0, 3.5]
             .iter().map(|h| BigDecimal::from(*h)).collect::<Vec<BigDecimal>>().as_slice()
         );
     }
+
+    #[test]
+    fn sv_case_verify_negative_rain() {
+        use crate::error::Error;
+        use crate::verify::Violation;
+        // Water does not flow on flat landscape, so negative water is found only by the final check.
+        let mut landscape = Landscape::create(vec![2.0, 2.0, 2.0]).unwrap();
+        landscape.set_verify(true).unwrap();
+        match landscape.rain_uniform(BigDecimal::from(-1.0), false) {
+            Err(Error::Verification(report)) => assert!(matches!(report.violations[..], [Violation::NegativeWater { point: 0, .. }, ..])),
+            other => panic!("expected verification error, got {:?}", other),
+        }
+    }
 }
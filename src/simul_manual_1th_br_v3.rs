@@ -0,0 +1,535 @@
+/*!
+ * Solve problem using manually written simulation working in main thread.
+ * Version 3: Exact simulation - connected points with equal water level are treated as one pool,
+ * performs calculation on BigRational, so every division (equal fraction, pool filling) is exact.
+ * Intended to be used as reference (oracle) when validating other algorithms.
+ */
+
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::fmt;
+
+use crate::error::{Error, Result};
+use crate::check_landscape;
+
+use crate::topology::{Boundary, Line1D, Topology};
+use crate::verify::{Report, Violation};
+
+use num_bigint::BigInt;
+use num_rational::BigRational;
+use num_traits::{Signed, ToPrimitive, Zero};
+
+/// Base unclehood type used for calculations during simulation in this module.
+type PointHeight = <Landscape as crate::Solver>::PointHeight;
+
+/// Arbitrary precision rational number.
+/// Wrapper around `BigRational` required because `Solver::PointHeight` must be convertible from `f64`.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Rational(pub BigRational);
+
+impl From<f64> for Rational {
+    /// Conversion is exact (every finite `f64` is a rational number).
+    /// Panics on NaN or infinity - use `Rational::try_from_f64()` for values which are not checked before.
+    fn from(h: f64) -> Self {
+        Rational(BigRational::from_float(h).expect("Finite number expected"))
+    }
+}
+
+impl Rational {
+    /// Converts `h` exactly, returns `Error::InvalidArgument` for NaN or infinity.
+    pub fn try_from_f64(h: f64) -> Result<Self> {
+        match BigRational::from_float(h) {
+            Some(r) => Ok(Rational(r)),
+            None => Err(Error::InvalidArgument(format!("Number must be finite: {}", h))),
+        }
+    }
+
+    /// Returns nearest `f64` value (may be not exact).
+    /// Numerator and denominator may be far beyond range of `f64` (while their ratio is not),
+    /// so quotient is calculated on integers scaled to 54 significant bits and then multiplied by power of 2.
+    pub fn to_f64(&self) -> f64 {
+        let (numer, denom) = (self.0.numer(), self.0.denom());
+        let shift = numer.bits() as i64 - denom.bits() as i64 - 54;
+        let quotient = if shift > 0 { numer / (denom << shift as usize) } else { (numer << (-shift) as usize) / denom };
+        // Power is split in two factors, so that none of them overflows before result does.
+        let half = (shift / 2) as i32;
+        quotient.to_f64().unwrap_or(f64::NAN) * 2f64.powi(half) * 2f64.powi(shift as i32 - half)
+    }
+}
+
+impl From<BigRational> for Rational {
+    fn from(r: BigRational) -> Self {
+        Rational(r)
+    }
+}
+
+impl fmt::Display for Rational {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl fmt::Debug for Rational {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// Represents entire 'world' where water is raining onto and flowing down from point (section in the paper) to point.
+pub struct Landscape<T: Topology = Line1D> {
+    topology: T,
+    points: Vec<Point>,
+    results: Vec<PointHeight>,
+    /// Water which has arrived at point (rain or inflow from higher points) but was not routed yet.
+    pending: Vec<BigRational>,
+    /// Marks members of currently processed pool (buffer re-used between calls).
+    in_pool: Vec<bool>,
+    /// Conditions at the edge of landscape (or drains) for every point.
+    outlets: Vec<Boundary<BigRational>>,
+    /// Amount of rain added to landscape during last `rain()` call.
+    rain_added: BigRational,
+    /// Amount of water which left landscape during last `rain()` call.
+    outflow: BigRational,
+    /// Simulated time (in hours) since landscape was created.
+    time: f64,
+    /// Checks invariants of simulation (see `verify` module).
+    verify: bool,
+}
+
+/// Entry of the queue of points with pending water, ordered by water level (highest first).
+#[derive(Debug, PartialEq, Eq)]
+struct PendingWater {
+    level: BigRational,
+    idx: usize,
+}
+
+impl PartialOrd for PendingWater {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for PendingWater {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // Lower index first on equal level, so that results do not depend on heap internals.
+        self.level.cmp(&other.level).then_with(|| other.idx.cmp(&self.idx))
+    }
+}
+
+impl Landscape {
+    /// Create Landscape object with points placed on the line (as in problem description).
+    /// `points` object is intentionally consumed to free memory as soon as possible.
+    #[allow(dead_code)]
+    pub fn create(ph: Vec<f64>) -> Result<Self> {
+        let topology = Line1D::new(ph.len());
+        Self::create_with_topology(ph, topology)
+    }
+
+    /// Create Landscape object.
+    /// Simulation in this module is exact, so `precision` is ignored.
+    /// Function is provided only for compatibility with other modules.
+    #[allow(dead_code)]
+    pub fn create_with_precision(ph: Vec<f64>, _precision: PointHeight) -> Result<Self> {
+        Self::create(ph)
+    }
+}
+
+impl<T: Topology> Landscape<T> {
+    /// Create Landscape object with points connected according to given `topology`.
+    /// `ph` must contain finite (possibly negative) height of every point of topology,
+    /// otherwise `Error::SizeMismatch` or `Error::NonFiniteHeight` is returned.
+    #[allow(dead_code)]
+    pub fn create_with_topology(ph: Vec<f64>, topology: T) -> Result<Self> {
+        check_landscape(&ph, topology.len())?;
+        let mut points = Vec::with_capacity(ph.len());
+        for h in &ph {
+            points.push(Point::with_height(Rational::from(*h).0));
+        }
+        let results = ph.into_iter().map(Rational::from).collect::<Vec<_>>();
+        let pending = vec![Zero::zero(); results.len()];
+        let in_pool = vec![false; results.len()];
+        let outlets = (0..results.len()).map(|i| topology.boundary(i).map(|h| Rational::from(h).0)).collect();
+        Ok(Landscape { topology, points, results, pending, in_pool, outlets, rain_added:Zero::zero(), outflow:Zero::zero(), time:0.0, verify:false })
+    }
+
+    /// Create Landscape object with points connected according to given `topology`.
+    /// Simulation in this module is exact, so `precision` is ignored.
+    #[allow(dead_code)]
+    pub fn create_with_topology_and_precision(ph: Vec<f64>, topology: T, _precision: PointHeight) -> Result<Self> {
+        Self::create_with_topology(ph, topology)
+    }
+
+    /// Marks point at `idx` index as drain - water flows from it outside of landscape (as to infinitely low neighbor).
+    #[allow(dead_code)]
+    pub fn set_drain(&mut self, idx: usize) {
+        self.outlets[idx] = Boundary::Outflow;
+    }
+
+    /// Determines directions in which water can flow from point at `idx` index.
+    fn neighbors(&self, idx: usize) -> T::Neighbors<'_> {
+        self.topology.neighbors(idx)
+    }
+
+    /// Fills `pool` with indexes of points connected to `idx` which have the same water level.
+    /// Members of pool are marked in `self.in_pool`.
+    fn collect_pool(&mut self, idx: usize, pool: &mut Vec<usize>) {
+        pool.clear();
+        pool.push(idx);
+        self.in_pool[idx] = true;
+        let mut i = 0;
+        while i < pool.len() {
+            for ni in self.topology.neighbors(pool[i]) {
+                if !self.in_pool[ni] && self.points[ni].level == self.points[idx].level {
+                    self.in_pool[ni] = true;
+                    pool.push(ni);
+                }
+            }
+            i += 1;
+        }
+    }
+
+    /// Function that determines how water is flowing thru landscape.
+    /// The same algorithm as in `simul_manual_1th_v3` module.
+    /// Please look at `README.md` for more information.
+    fn stabilize_water(&mut self) -> Result<()> {
+        // Volume of water and state function are calculated only in verification mode.
+        let expected_volume = if self.verify { self.calc_volume() + &self.outflow } else { Zero::zero() };
+        let base = if self.verify { self.points.iter().map(|p| &p.ground).min().cloned().unwrap_or_else(Zero::zero) } else { Zero::zero() };
+        let state = if self.verify { self.calc_state(&base) } else { Zero::zero() };
+
+        let mut queue = BinaryHeap::new();
+        for (idx, pw) in self.pending.iter().enumerate() {
+            if pw.is_positive() {
+                queue.push(PendingWater { level: self.points[idx].level.clone(), idx });
+            }
+        }
+
+        let mut pool = Vec::new();
+        let mut outlets = Vec::new();
+        while let Some(PendingWater { level, idx }) = queue.pop() {
+            if !self.pending[idx].is_positive() || self.points[idx].level != level {
+                // Pending water was already collected by pool processed before, or the entry is outdated.
+                continue;
+            }
+            self.collect_pool(idx, &mut pool);
+
+            let mut volume = BigRational::zero();
+            outlets.clear();
+            let mut rim: Option<BigRational> = None;
+            let mut sinks = 0;
+            for pi in &pool {
+                volume += std::mem::replace(&mut self.pending[*pi], Zero::zero());
+                match &self.outlets[*pi] {
+                    Boundary::Wall => {},
+                    Boundary::Outflow => sinks += 1,
+                    Boundary::Reservoir(rl) if *rl <= level => sinks += 1,
+                    Boundary::Reservoir(rl) => if rim.as_ref().is_none_or(|r| rl < r) {
+                        rim = Some(rl.clone());
+                    },
+                }
+                for ni in self.neighbors(*pi) {
+                    if self.in_pool[ni] {
+                        continue;
+                    }
+                    let nh = &self.points[ni].level;
+                    if *nh < level {
+                        outlets.push(ni);
+                    } else if rim.as_ref().is_none_or(|r| nh < r) {
+                        rim = Some(nh.clone());
+                    }
+                }
+            }
+            for pi in &pool {
+                self.in_pool[*pi] = false;
+            }
+            // Outlet adjacent to several members of pool gets its part only once.
+            outlets.sort_unstable();
+            outlets.dedup();
+
+            if !outlets.is_empty() || sinks > 0 {
+                let equal_fraction = volume / BigInt::from(outlets.len() + sinks);
+                for ni in &outlets {
+                    self.pending[*ni] += &equal_fraction;
+                    queue.push(PendingWater { level: self.points[*ni].level.clone(), idx: *ni });
+                }
+                self.outflow += equal_fraction * BigInt::from(sinks);
+                continue;
+            }
+
+            let area = BigRational::from_integer(BigInt::from(pool.len()));
+            let new_level = &level + &volume / &area;
+            match rim {
+                Some(rim) if new_level >= rim => {
+                    // Pool reaches its rim: it merges with the lowest neighbor (or spills into reservoir) and the rest of water is processed again.
+                    for pi in &pool {
+                        self.points[*pi].level = rim.clone();
+                    }
+                    let rest = volume - (&rim - &level) * area;
+                    if rest.is_positive() {
+                        self.pending[idx] = rest;
+                        queue.push(PendingWater { level: rim, idx });
+                    }
+                },
+                _ => {
+                    for pi in &pool {
+                        self.points[*pi].level = new_level.clone();
+                    }
+                },
+            }
+        }
+
+        if self.verify {
+            self.check_state(expected_volume, &base, state)?;
+        }
+        Ok(())
+    }
+
+    /// Checks that volume of water with outflow is equal to `expected_volume`, that state function did not increase from `state`
+    /// (water only flows down, pending water counted at point it fell onto) and that no water can flow anymore (verification mode).
+    /// Calculations are exact, so strict comparisons are used.
+    fn check_state(&self, expected_volume: BigRational, base: &BigRational, state: BigRational) -> Result<()> {
+        let mut report = Report::new(self.time, 0);
+        let volume = self.calc_volume() + &self.outflow;
+        if volume != expected_volume {
+            report.violations.push(Violation::MassNotConserved { expected: Rational(expected_volume).to_f64(), actual: Rational(volume).to_f64() });
+        }
+        let new_state = self.calc_state(base);
+        if new_state > state {
+            report.violations.push(Violation::StateNotDecreasing { before: Rational(state).to_f64(), after: Rational(new_state.clone()).to_f64() });
+        }
+        let state_lbound = self.calc_state_lbound(base);
+        if new_state < state_lbound {
+            report.violations.push(Violation::StateBelowBound { state: Rational(new_state).to_f64(), bound: Rational(state_lbound).to_f64() });
+        }
+        for (pi, p) in self.points.iter().enumerate() {
+            if p.level < p.ground {
+                report.violations.push(Violation::NegativeWater { point: pi, water: Rational(&p.level - &p.ground).to_f64() });
+            }
+            if p.level == p.ground {
+                continue;
+            }
+            for ni in self.neighbors(pi) {
+                if self.points[ni].level < p.level {
+                    report.violations.push(Violation::UnstableWater {
+                        point: pi, level: Rational(p.level.clone()).to_f64(),
+                        neighbor: ni, neighbor_level: Rational(self.points[ni].level.clone()).to_f64(),
+                    });
+                }
+            }
+        }
+        report.into_result()
+    }
+
+    /// Returns state function with levels (pending water included) counted from `base`.
+    /// Unlike `verify::state()` it uses square of level, which is calculated exactly on rational numbers (non-integer power is not).
+    fn calc_state(&self, base: &BigRational) -> BigRational {
+        self.points.iter().zip(&self.pending).map(|(p, pw)| {
+            let h = &p.level + pw - base;
+            &h * &h
+        }).sum()
+    }
+
+    /// Returns state function of dry landscape - lower bound of `calc_state()`.
+    fn calc_state_lbound(&self, base: &BigRational) -> BigRational {
+        self.points.iter().map(|p| {
+            let h = &p.ground - base;
+            &h * &h
+        }).sum()
+    }
+
+    /// Returns total amount of water stored in landscape (pending water included).
+    fn calc_volume(&self) -> BigRational {
+        self.points.iter().map(|p| &p.level - &p.ground).sum::<BigRational>() + self.pending.iter().sum::<BigRational>()
+    }
+}
+
+impl<T: Topology> crate::Solver for Landscape<T> {
+    /// Base unclehood type used for calculations during simulation in this module.
+    type PointHeight = Rational;
+
+    /// Simulates `dt` hours of falling rain.
+    fn rain_for(&mut self, dt: f64, rain_distr: impl Fn(usize) -> PointHeight + Sync, return_result: bool) -> Result<&[PointHeight]> {
+        self.rain_added = Zero::zero();
+        self.outflow = Zero::zero();
+        let dt_br = Rational::try_from_f64(dt)?.0;
+        for (idx, pw) in self.pending.iter_mut().enumerate() {
+            let rain = rain_distr(idx).0 * &dt_br;
+            self.rain_added += &rain;
+            *pw += rain;
+        }
+
+        self.stabilize_water()?;
+        self.time += dt;
+
+        if return_result {
+            for (i, p) in self.points.iter().enumerate() {
+                self.results[i] = Rational(p.level.clone());
+            }
+            Ok(&self.results[..])
+        } else {
+            Ok(&[])
+        }
+    }
+
+    /// Returns simulation precision (0 - simulation is exact).
+    fn precision(&self) -> PointHeight { Rational(Zero::zero()) }
+
+    /// Returns amount of water which left landscape during last `rain()` call.
+    fn outflow(&self) -> PointHeight { Rational(self.outflow.clone()) }
+
+    /// Returns simulated time (in hours).
+    fn time(&self) -> f64 { self.time }
+
+    /// Returns amount of rain added to landscape during last `rain()` call.
+    fn rain_added(&self) -> PointHeight { Rational(self.rain_added.clone()) }
+
+    /// Returns amount of water stored in landscape.
+    fn stored(&self) -> PointHeight {
+        Rational(self.calc_volume())
+    }
+
+    /// Enables checking of invariants of simulation.
+    fn set_verify(&mut self, verify: bool) -> Result<()> {
+        self.verify = verify;
+        Ok(())
+    }
+}
+
+/// Represents point (section) on landscape.
+/// Water level is stored (not water amount) - like in `simul_manual_1th_v3` module.
+#[derive(Debug, Clone)]
+struct Point {
+    ground: BigRational,
+    level: BigRational,
+}
+
+impl Point {
+    /// Point constructor
+    fn with_height(h: BigRational) -> Self {
+        Point {
+            ground: h.clone(),
+            level: h,
+        }
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////////////////////////
+// Tests
+//
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::*;
+
+    fn ratio(numer: i64, denom: i64) -> Rational {
+        Rational(BigRational::new(BigInt::from(numer), BigInt::from(denom)))
+    }
+
+    fn compare(left: &[PointHeight], right: &[f64]) {
+        let right = right.iter().map(|h| Rational::from(*h)).collect::<Vec<_>>();
+        assert_eq!(left, &right[..]);
+    }
+
+    #[test]
+    fn to_f64() {
+        assert_eq!(ratio(-7, 2).to_f64(), -3.5);
+        assert_eq!(ratio(1, 3).to_f64(), 1.0 / 3.0);
+        assert_eq!(Rational::from(1e-300).to_f64(), 1e-300);
+        // Numerator and denominator do not fit into `f64`, their ratio does.
+        let big = num_traits::pow(BigInt::from(10), 400);
+        assert_eq!(Rational(BigRational::new(&big * BigInt::from(5), big.clone() * BigInt::from(2))).to_f64(), 2.5);
+        assert_eq!(Rational(BigRational::new(BigInt::from(1), big.clone())).to_f64(), 0.0);
+        assert_eq!(Rational(BigRational::from_integer(big)).to_f64(), f64::INFINITY);
+    }
+
+    #[test]
+    fn try_from_f64() {
+        assert_eq!(Rational::try_from_f64(-3.5), Ok(ratio(-7, 2)));
+        for h in &[f64::NAN, f64::INFINITY, f64::NEG_INFINITY] {
+            assert!(matches!(Rational::try_from_f64(*h), Err(Error::InvalidArgument(_))));
+        }
+        let mut landscape = Landscape::create(vec![1.0, 2.0]).unwrap();
+        assert!(matches!(landscape.rain_for(f64::NAN, |_| ratio(1, 1), false), Err(Error::InvalidArgument(_))));
+    }
+
+    #[test]
+    fn sv_case_sample() {
+        let points = vec![3.0, 1.0, 6.0, 4.0, 8.0, 9.0];
+        let mut landscape = Landscape::create(points).unwrap();
+        let result = landscape.rain_uniform(RAIN_DENSITY.into(), true).unwrap();
+        compare(result, &[4.0, 4.0, 6.0, 6.0, 8.0, 9.0]);
+        let result = landscape.rain_uniform(RAIN_DENSITY.into(), true).unwrap();
+        compare(result, &[6.5, 6.5, 6.5, 6.5, 8.0, 9.0]);
+    }
+
+    #[test]
+    fn sv_case_mail2() {
+        let points = vec![8.0, 8.0, 1.0];
+        let mut landscape = Landscape::create(points).unwrap();
+        let result = landscape.rain_uniform(RAIN_DENSITY.into(), true).unwrap();
+        compare(result, &[8.0, 8.0, 4.0]);
+        landscape.rain_uniform(RAIN_DENSITY.into(), false).unwrap();
+        let result = landscape.rain_uniform(RAIN_DENSITY.into(), true).unwrap();
+        assert_eq!(result, &[ratio(26, 3), ratio(26, 3), ratio(26, 3)]);
+    }
+
+    #[test]
+    fn sv_case_verify() {
+        let points: Vec<f64> = (0..50).map(|i| ((i * 37) % 23) as f64).collect();
+        let mut landscape = Landscape::create(points).unwrap();
+        landscape.set_drain(25);
+        landscape.set_verify(true).unwrap();
+        for _ in 0..5 {
+            landscape.rain_uniform(RAIN_DENSITY.into(), false).unwrap();
+        }
+        // Pit on 2D grid filled by all its neighbors at once.
+        use crate::topology::{Grid2D, Neighborhood};
+        let mut landscape = Landscape::create_with_topology(vec![3.0, 3.0, 3.0, 3.0, 0.0, 3.0, 3.0, 3.0, 3.0], Grid2D::new(3, 3, Neighborhood::Moore)).unwrap();
+        landscape.set_verify(true).unwrap();
+        let result = landscape.rain_uniform(RAIN_DENSITY.into(), true).unwrap();
+        assert!(result.iter().all(|h| *h == ratio(11, 3)));
+    }
+
+    #[test]
+    fn sv_case_mail4() {
+        let points = vec![8.0, 4.0, 8.0, 8.0, 1.0];
+        let mut landscape = Landscape::create(points).unwrap();
+        let result = landscape.rain_uniform(RAIN_DENSITY.into(), true).unwrap();
+        compare(result, &[8.0, 7.0, 8.0, 8.0, 3.0]);
+    }
+
+    #[test]
+    fn sv_case_mail5() {
+        let points = vec![1.0, 8.0, 8.0, 8.0, 1.0];
+        let mut landscape = Landscape::create(points).unwrap();
+        let result = landscape.rain_uniform(RAIN_DENSITY.into(), true).unwrap();
+        compare(result, &[3.5, 8.0, 8.0, 8.0, 3.5]);
+    }
+
+    #[test]
+    fn sv_case_readme_4111() {
+        let points = vec![4.0, 1.0, 1.0, 1.0];
+        let mut landscape = Landscape::create(points).unwrap();
+        let result = landscape.rain_uniform(RAIN_DENSITY.into(), true).unwrap();
+        assert_eq!(result, &[ratio(4, 1), ratio(7, 3), ratio(7, 3), ratio(7, 3)]);
+    }
+
+    #[test]
+    fn sv_case_pool_thirds() {
+        // Pool of 3 points filled by 1/3 units of water, which is not exact in f64.
+        let points = vec![5.0, 1.0, 1.0, 1.0, 5.0];
+        let mut landscape = Landscape::create(points).unwrap();
+        let result = landscape.rain(|idx| if idx == 0 { 1.0.into() } else { 0.0.into() }, true).unwrap();
+        assert_eq!(result, &[ratio(5, 1), ratio(4, 3), ratio(4, 3), ratio(4, 3), ratio(5, 1)]);
+    }
+
+    #[test]
+    fn sv_case_drain() {
+        let mut landscape = Landscape::create(vec![5.0, 1.0, 5.0, 2.0, 1.0]).unwrap();
+        landscape.set_drain(1);
+        let result = landscape.rain_uniform(RAIN_DENSITY.into(), true).unwrap();
+        assert_eq!(result, &[ratio(5, 1), ratio(1, 1), ratio(5, 1), ratio(11, 4), ratio(11, 4)]);
+        assert_eq!(landscape.outflow(), ratio(5, 2));
+    }
+}
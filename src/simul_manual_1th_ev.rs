@@ -0,0 +1,376 @@
+/*!
+ * Solve problem using manually written simulation working in main thread.
+ * Version EV: Event driven simulation in continuous time - rain falls continuously and flows immediately.
+ * Connected points with equal water level are treated as one pool (like in version 3). As long as no pool
+ * reaches its rim, every pool is filled with constant rate, so water levels change linearly in time.
+ * Simulation jumps from one such event (pool reaching its rim and merging with neighbor) to the next one,
+ * so its cost does not depend on simulated time, but on number of events.
+ */
+
+use crate::error::Result;
+use crate::{check_landscape, check_time};
+
+use crate::topology::{Boundary, Line1D, Topology};
+
+/// Base unclehood type used for calculations during simulation in this module.
+type PointHeight = <Landscape as crate::Solver>::PointHeight;
+
+/// Represents entire 'world' where water is raining onto and flowing down from point (section in the paper) to point.
+pub struct Landscape<T: Topology = Line1D> {
+    topology: T,
+    /// Ground level of every point.
+    ground: Vec<PointHeight>,
+    /// Water level of every point.
+    levels: Vec<PointHeight>,
+    results: Vec<PointHeight>,
+    /// Conditions at the edge of landscape (or drains) for every point.
+    outlets: Vec<Boundary<PointHeight>>,
+    /// Amount of rain added to landscape during last `rain()` call.
+    rain_added: PointHeight,
+    /// Amount of water which left landscape during last `rain()` call.
+    outflow: PointHeight,
+    /// Simulated time (in hours) since landscape was created.
+    time: f64,
+    /// Index of pool (in `pools`) every point belongs to (buffer re-used between calls).
+    pool_of: Vec<usize>,
+    /// Members of pools - members of every pool are stored one after another (buffer re-used between calls).
+    members: Vec<usize>,
+    /// Pools in order from the highest water level to the lowest (buffer re-used between calls).
+    pools: Vec<Pool>,
+}
+
+/// Connected points with equal water level.
+#[derive(Debug)]
+struct Pool {
+    level: PointHeight,
+    /// Range of members of pool in `Landscape::members`.
+    first: usize,
+    size: usize,
+    /// Water flowing into pool (rain onto its members and inflow from higher pools) per hour.
+    inflow: PointHeight,
+}
+
+/// Used in place of pool index for points not assigned to any pool yet.
+const NO_POOL: usize = usize::MAX;
+
+impl Landscape {
+    /// Create Landscape object with points placed on the line (as in problem description).
+    /// `points` object is intentionally consumed to free memory as soon as possible.
+    /// In this case however it is moved and re-used as buffer for results.
+    #[allow(dead_code)]
+    pub fn create(ph: Vec<f64>) -> Result<Self> {
+        let topology = Line1D::new(ph.len());
+        Self::create_with_topology(ph, topology)
+    }
+
+    /// Create Landscape object.
+    /// Simulation in this module is exact, so `precision` is ignored.
+    /// Function is provided only for compatibility with other modules.
+    #[allow(dead_code)]
+    pub fn create_with_precision(ph: Vec<f64>, _precision: PointHeight) -> Result<Self> {
+        Self::create(ph)
+    }
+}
+
+impl<T: Topology> Landscape<T> {
+    /// Create Landscape object with points connected according to given `topology`.
+    /// `ph` must contain finite (possibly negative) height of every point of topology,
+    /// otherwise `Error::SizeMismatch` or `Error::NonFiniteHeight` is returned.
+    #[allow(dead_code)]
+    pub fn create_with_topology(ph: Vec<f64>, topology: T) -> Result<Self> {
+        check_landscape(&ph, topology.len())?;
+        let outlets = (0..ph.len()).map(|i| topology.boundary(i)).collect();
+        Ok(Landscape {
+            topology,
+            ground: ph.clone(),
+            levels: ph.clone(),
+            pool_of: vec![NO_POOL; ph.len()],
+            members: Vec::with_capacity(ph.len()),
+            pools: Vec::new(),
+            results: ph,
+            outlets,
+            rain_added: 0.0,
+            outflow: 0.0,
+            time: 0.0,
+        })
+    }
+
+    /// Create Landscape object with points connected according to given `topology`.
+    /// Simulation in this module is exact, so `precision` is ignored.
+    #[allow(dead_code)]
+    pub fn create_with_topology_and_precision(ph: Vec<f64>, topology: T, _precision: PointHeight) -> Result<Self> {
+        Self::create_with_topology(ph, topology)
+    }
+
+    /// Marks point at `idx` index as drain - water flows from it outside of landscape (as to infinitely low neighbor).
+    #[allow(dead_code)]
+    pub fn set_drain(&mut self, idx: usize) {
+        self.outlets[idx] = Boundary::Outflow;
+    }
+
+    /// Divides landscape into pools, ordered from the highest water level to the lowest one.
+    fn collect_pools(&mut self) {
+        let mut order: Vec<usize> = (0..self.levels.len()).collect();
+        let levels = &self.levels;
+        order.sort_by(|a, b| levels[*b].total_cmp(&levels[*a]));
+
+        self.pools.clear();
+        self.members.clear();
+        for p in self.pool_of.iter_mut() {
+            *p = NO_POOL;
+        }
+        for idx in order {
+            if self.pool_of[idx] != NO_POOL {
+                continue;
+            }
+            let pool = self.pools.len();
+            let first = self.members.len();
+            let level = self.levels[idx];
+            self.pool_of[idx] = pool;
+            self.members.push(idx);
+            let mut i = first;
+            while i < self.members.len() {
+                for ni in self.topology.neighbors(self.members[i]) {
+                    if self.pool_of[ni] == NO_POOL && self.levels[ni] == level {
+                        self.pool_of[ni] = pool;
+                        self.members.push(ni);
+                    }
+                }
+                i += 1;
+            }
+            self.pools.push(Pool { level, first, size: self.members.len() - first, inflow: 0.0 });
+        }
+    }
+
+    /// Simulates `dt` hours of rain falling continuously with intensity given by `rates` (amount per hour for every point).
+    /// Water is routed like in version 3: pool which has lower neighbors (outlets) passes all its inflow to them in equal parts,
+    /// otherwise it is filled up, until it reaches its rim (the lowest neighbor) - then pools are collected again.
+    /// Please look at `README.md` for more information.
+    fn advance(&mut self, mut dt: f64, rates: &[PointHeight]) -> Result<()> {
+        // Rate at which every pool level rises (0 for pools passing water to outlets) and level at which it merges with neighbor.
+        let mut rises = Vec::new();
+        let mut rims = Vec::new();
+        let mut outlets = Vec::new();
+        loop {
+            self.collect_pools();
+            for pool in &mut self.pools {
+                pool.inflow = self.members[pool.first..pool.first + pool.size].iter().map(|idx| rates[*idx]).sum();
+            }
+
+            rises.clear();
+            rims.clear();
+            let mut outflow_rate = 0.0;
+            let mut next_event = f64::INFINITY;
+            for pi in 0..self.pools.len() {
+                let (level, first, size, inflow) = {
+                    let pool = &self.pools[pi];
+                    (pool.level, pool.first, pool.size, pool.inflow)
+                };
+                outlets.clear();
+                let mut sinks = 0;
+                let mut rim = f64::INFINITY;
+                for mi in first..first + size {
+                    let idx = self.members[mi];
+                    match self.outlets[idx] {
+                        Boundary::Wall => {},
+                        Boundary::Outflow => sinks += 1,
+                        Boundary::Reservoir(rl) if rl <= level => sinks += 1,
+                        Boundary::Reservoir(rl) => if rl < rim {
+                            rim = rl;
+                        },
+                    }
+                    for ni in self.topology.neighbors(idx) {
+                        let nh = self.levels[ni];
+                        if nh < level {
+                            outlets.push(ni);
+                        } else if nh > level && nh < rim {
+                            rim = nh;
+                        }
+                    }
+                }
+
+                // Outlet adjacent to several members of pool gets its part only once.
+                outlets.sort_unstable();
+                outlets.dedup();
+                if !outlets.is_empty() || sinks > 0 {
+                    let equal_fraction = inflow / (outlets.len() + sinks) as PointHeight;
+                    for ni in &outlets {
+                        self.pools[self.pool_of[*ni]].inflow += equal_fraction;
+                    }
+                    outflow_rate += equal_fraction * sinks as PointHeight;
+                    rises.push(0.0);
+                } else {
+                    let rise = inflow / size as PointHeight;
+                    if rise > 0.0 {
+                        let to_rim = (rim - level) / rise;
+                        if to_rim < next_event {
+                            next_event = to_rim;
+                        }
+                    }
+                    rises.push(rise);
+                }
+                rims.push(rim);
+            }
+
+            let step = if next_event < dt { next_event } else { dt };
+            for (pi, pool) in self.pools.iter().enumerate() {
+                if rises[pi] <= 0.0 {
+                    continue;
+                }
+                // Pool which reaches its rim gets exactly its level, so that it is joined with neighbor when pools are collected again.
+                let mut new_level = pool.level + rises[pi] * step;
+                if step == next_event && (rims[pi] - pool.level) / rises[pi] <= step || new_level > rims[pi] {
+                    new_level = rims[pi];
+                }
+                for mi in pool.first..pool.first + pool.size {
+                    self.levels[self.members[mi]] = new_level;
+                }
+            }
+            self.outflow += outflow_rate * step;
+
+            if step >= dt {
+                break;
+            }
+            dt -= step;
+        }
+        Ok(())
+    }
+}
+
+impl<T: Topology> crate::Solver for Landscape<T> {
+    /// Base unclehood type used for calculations during simulation in this module.
+    type PointHeight = f64;
+
+    /// Simulates `dt` hours of rain falling continuously.
+    fn rain_for(&mut self, dt: f64, rain_distr: impl Fn(usize) -> PointHeight + Sync, return_result: bool) -> Result<&[PointHeight]> {
+        self.outflow = 0.0;
+        let rates: Vec<PointHeight> = (0..self.levels.len()).map(rain_distr).collect();
+        self.rain_added = rates.iter().sum::<PointHeight>() * dt;
+        self.advance(dt, &rates)?;
+        self.time += dt;
+
+        if return_result {
+            self.results.copy_from_slice(&self.levels);
+            Ok(&self.results[..])
+        } else {
+            Ok(&[])
+        }
+    }
+
+    /// Jumps directly to time `t` - cost depends on number of pool merges, not on `t`.
+    fn levels_at(&mut self, t: f64, density: PointHeight) -> Result<&[PointHeight]> {
+        check_time(t, self.time)?;
+        self.rain_for(t - self.time, |_| density, true)
+    }
+
+    /// Returns simulation precision (0 - simulation is exact).
+    fn precision(&self) -> PointHeight { 0.0 }
+
+    /// Returns amount of water which left landscape during last `rain()` call.
+    fn outflow(&self) -> PointHeight { self.outflow }
+
+    /// Returns simulated time (in hours).
+    fn time(&self) -> f64 { self.time }
+
+    /// Returns amount of rain added to landscape during last `rain()` call.
+    fn rain_added(&self) -> PointHeight { self.rain_added }
+
+    /// Returns amount of water stored in landscape.
+    fn stored(&self) -> PointHeight {
+        self.levels.iter().zip(&self.ground).map(|(l, g)| l - g).sum()
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////////////////////////
+// Tests
+//
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::*;
+    use rand::{Rng, SeedableRng};
+
+    fn compare(left: &[PointHeight], right: &[PointHeight]) {
+        assert_eq!(left.len(), right.len());
+        for (i, _r) in left.iter().enumerate() {
+            if (left[i] - right[i]).abs() > 1e-9 * (1.0 + right[i].abs()) {
+                panic!("left[{}]={} != right[{}]={}", i, left[i], i, right[i]);
+            }
+        }
+    }
+
+    #[test]
+    fn sv_case_sample() {
+        let mut landscape = Landscape::create(vec![3.0, 1.0, 6.0, 4.0, 8.0, 9.0]).unwrap();
+        let result = landscape.rain_uniform(RAIN_DENSITY, true).unwrap();
+        compare(result, &[4.0, 4.0, 6.0, 6.0, 8.0, 9.0]);
+        let result = landscape.rain_for(0.5, |_| RAIN_DENSITY, true).unwrap();
+        compare(result, &[5.5, 5.5, 6.0, 6.0, 8.0, 9.0]);
+    }
+
+    #[test]
+    fn sv_case_shared_outlet() {
+        use crate::topology::{Grid2D, Neighborhood};
+        // Point 6 is adjacent to both members of pool of points 1 and 2, point 0 only to one of them - they get equal inflow.
+        let points = vec![0.0, 5.0, 5.0, 9.0, 9.0, 9.0, 0.0, 9.0];
+        let mut landscape = Landscape::create_with_topology(points, Grid2D::new(2, 4, Neighborhood::Moore)).unwrap();
+        let result = landscape.rain(|i| if i == 1 || i == 2 { 1.0 } else { 0.0 }, true).unwrap();
+        compare(result, &[1.0, 5.0, 5.0, 9.0, 9.0, 9.0, 1.0, 9.0]);
+    }
+
+    #[test]
+    fn sv_case_levels_at() {
+        let mut landscape = Landscape::create(vec![8.0, 8.0, 1.0]).unwrap();
+        let result = landscape.levels_at(3.0, RAIN_DENSITY).unwrap();
+        let level = 8.0 + 2.0 / 3.0;
+        compare(result, &[level, level, level]);
+        let result = landscape.levels_at(10_000.0, RAIN_DENSITY).unwrap();
+        let level = (3.0 * 10_000.0 + 17.0) / 3.0;
+        compare(result, &[level, level, level]);
+        assert!(matches!(landscape.levels_at(1.0, RAIN_DENSITY), Err(Error::TimeBeforeCurrent { .. })));
+        assert!(matches!(landscape.levels_at(f64::NAN, RAIN_DENSITY), Err(Error::InvalidArgument(_))));
+        assert!(matches!(landscape.levels_at(f64::INFINITY, RAIN_DENSITY), Err(Error::InvalidArgument(_))));
+    }
+
+    #[test]
+    fn sv_case_boundaries() {
+        use crate::topology::Boundary;
+        let mut line = Line1D::new(3);
+        line.set_boundaries(Boundary::Wall, Boundary::Reservoir(0.5));
+        let mut landscape = Landscape::create_with_topology(vec![0.0, 0.0, 0.0], line).unwrap();
+        let result = landscape.rain_uniform(RAIN_DENSITY, true).unwrap();
+        compare(result, &[0.5, 0.5, 0.5]);
+        assert!((landscape.outflow() - 1.5).abs() < 1e-9);
+
+        let mut landscape = Landscape::create(vec![5.0, 1.0, 5.0, 2.0]).unwrap();
+        landscape.set_drain(1);
+        let result = landscape.rain_uniform(RAIN_DENSITY, true).unwrap();
+        compare(result, &[5.0, 1.0, 5.0, 3.5]);
+        assert!((landscape.outflow() - 2.5).abs() < 1e-9);
+    }
+
+    /// Results do not depend on steps in which time is advanced and water volume is conserved.
+    /// Note: results may differ from version 3 where all rain of the hour falls at once - e.g. for `[0,3,3,1,3,0]`
+    /// rain falling onto the plateau after middle pool is filled up is divided by outlets of merged pool.
+    #[test]
+    fn sv_case_continuity() {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(5);
+        for _ in 0..20 {
+            let points: Vec<f64> = (0..50).map(|_| rng.gen_range(0, 20) as f64).collect();
+            let volume: f64 = points.iter().sum();
+            let mut landscape = Landscape::create(points.clone()).unwrap();
+            let mut reference = Landscape::create(points).unwrap();
+            for n in 1..=5 {
+                let expected = reference.levels_at(n as f64, RAIN_DENSITY).unwrap().to_vec();
+                landscape.rain_for(0.25, |_| RAIN_DENSITY, false).unwrap();
+                landscape.rain_for(0.75, |_| RAIN_DENSITY, false).unwrap();
+                let result = landscape.levels_at(n as f64, RAIN_DENSITY).unwrap();
+                compare(result, &expected);
+                let stored: f64 = result.iter().sum::<f64>() - volume;
+                assert!((stored - 50.0 * n as f64).abs() < 1e-9 * volume);
+                assert!((landscape.stored() - stored).abs() < 1e-9 * volume);
+            }
+        }
+    }
+}
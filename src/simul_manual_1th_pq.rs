@@ -0,0 +1,208 @@
+/*!
+ * Solve problem using manually written simulation working in main thread.  
+ * Priority queue version: points evaluated from highest water level to lowest (levels change with every flow),
+ * flows from point are applied immediately, so points evaluated later see levels after them.
+ * It is an approximation - it stops in different state than `simul_manual_1th_v2` and differs more from exact `simul_manual_1th_v3`.
+ */
+
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+use crate::iterative::{IterativeLandscape, Model, PointHeight, Scheduler, WaterUpdate, OUTSIDE};
+use crate::topology::{Line1D, Topology};
+
+/// Represents entire 'world' where water is raining onto and flowing down from point (section in the paper) to point.
+pub type Landscape<T = Line1D> = IterativeLandscape<LevelOrder, T>;
+
+/// Point waiting for evaluation in priority queue, ordered by its water level at the time it was queued.
+#[derive(Debug)]
+struct QueuedPoint {
+    level: PointHeight,
+    idx: usize,
+}
+
+impl PartialEq for QueuedPoint {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for QueuedPoint {}
+
+impl PartialOrd for QueuedPoint {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for QueuedPoint {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // Lower index first on equal level, so that results do not depend on heap internals.
+        self.level.total_cmp(&other.level).then_with(|| other.idx.cmp(&self.idx))
+    }
+}
+
+/// Evaluates each wet point once per iteration, in order of current water levels from the highest one.
+/// Points with the same level (within precision) are taken from the queue together, their flows are calculated
+/// from current levels and applied at once (so water is divided equally as in `simul_manual_1th_v2`),
+/// and neighbors which received water are queued again with their new levels (stale entries of queue are skipped).
+pub struct LevelOrder {
+    queue: BinaryHeap<QueuedPoint>,
+    /// Points with the same level evaluated together.
+    batch: Vec<usize>,
+    /// Number of the last iteration in which point was evaluated.
+    visited: Vec<usize>,
+    send_water_to: Vec<usize>, // TODO: possibly use smallvec or tiny_vec
+}
+
+impl LevelOrder {
+    /// Returns `true` if queued point was not evaluated in current `iteration` yet and its level did not change since it was queued.
+    fn is_pending<T: Topology>(&self, model: &Model<T>, queued: &QueuedPoint, iteration: usize) -> bool {
+        self.visited[queued.idx] != iteration && queued.level == model.points[queued.idx].get_height()
+    }
+}
+
+impl<T: Topology> Scheduler<T> for LevelOrder {
+    /// Flows are applied immediately, so water would travel thru landscape in one step anyway.
+    const LIMITED_FLOW: bool = false;
+
+    fn new(model: &Model<T>) -> Self {
+        LevelOrder { queue: BinaryHeap::new(), batch: Vec::new(), visited: vec![0; model.points.len()], send_water_to: Vec::new() }
+    }
+
+    fn start(&mut self, _model: &Model<T>) {
+        // Iterations are counted from 1 in every `stabilize_water()` call.
+        self.visited.iter_mut().for_each(|v| *v = 0);
+    }
+
+    fn iteration(&mut self, model: &mut Model<T>, dt: f64, water_update: &mut Vec<WaterUpdate>) {
+        let iteration = model.stats.iterations;
+        self.queue.extend(model.points.iter().enumerate()
+            .filter(|(_, p)| p.water > model.precision)
+            .map(|(idx, p)| QueuedPoint { level: p.get_height(), idx }));
+        while let Some(top) = self.queue.pop() {
+            if !self.is_pending(model, &top, iteration) {
+                continue;
+            }
+            self.batch.clear();
+            self.batch.push(top.idx);
+            self.visited[top.idx] = iteration;
+            while let Some(next) = self.queue.peek() {
+                if next.level + model.precision < top.level {
+                    break;
+                }
+                let next = self.queue.pop().unwrap();
+                if self.is_pending(model, &next, iteration) {
+                    self.batch.push(next.idx);
+                    self.visited[next.idx] = iteration;
+                }
+            }
+            model.stats.visits += self.batch.len();
+            let first_update = water_update.len();
+            for pi in &self.batch {
+                model.calc_flows(*pi, dt, &mut self.send_water_to, water_update);
+            }
+            // Flows are applied at once, so that points evaluated later see new levels.
+            for wu in &water_update[first_update..] {
+                model.apply(wu);
+                if wu.to_idx != OUTSIDE && self.visited[wu.to_idx] != iteration {
+                    self.queue.push(QueuedPoint { level: model.points[wu.to_idx].get_height(), idx: wu.to_idx });
+                }
+            }
+        }
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////////////////////////
+// Tests
+//
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::*;
+
+    fn compare(precision: &PointHeight, left: &[PointHeight], right: &[PointHeight]) {
+        assert_eq!(left.len(), right.len());
+        for (i, (l, r)) in left.iter().zip(right).enumerate() {
+            assert!((l - r).abs() <= precision * left.len() as PointHeight, "left[{}]={} != right[{}]={}", i, l, i, r);
+        }
+    }
+
+    #[test]
+    fn sv_case_sample() {
+        for (points, expected) in [
+            (vec![3.0, 1.0, 6.0, 4.0, 8.0, 9.0], vec![4.0, 4.0, 6.0, 6.0, 8.0, 9.0]),
+            (vec![8.0, 8.0, 1.0], vec![8.0, 8.0, 4.0]),
+            (vec![1.0, 8.0, 8.0, 1.0], vec![3.0, 8.0, 8.0, 3.0]),
+            (vec![8.0, 4.0, 8.0, 8.0, 1.0], vec![8.0, 7.0, 8.0, 8.0, 3.0]),
+            // Points of plateau have the same level, so they are evaluated together and water is divided equally.
+            (vec![1.0, 8.0, 8.0, 8.0, 1.0], vec![3.5, 8.0, 8.0, 8.0, 3.5]),
+        ] {
+            let mut landscape = Landscape::create(points).unwrap();
+            let prec = landscape.precision();
+            compare(&prec, landscape.rain_uniform(RAIN_DENSITY, true).unwrap(), &expected);
+        }
+    }
+
+    #[test]
+    fn sv_case_verify() {
+        let points: Vec<f64> = (0..200).map(|i| ((i * 37) % 23) as f64).collect();
+        let mut landscape = Landscape::create(points).unwrap();
+        landscape.set_drain(100);
+        landscape.set_verify(true).unwrap();
+        for _ in 0..5 {
+            landscape.rain_uniform(RAIN_DENSITY, false).unwrap();
+        }
+    }
+
+    #[test]
+    fn sv_case_boundaries() {
+        use crate::topology::Boundary;
+        let mut line = Line1D::new(3);
+        line.set_boundaries(Boundary::Wall, Boundary::Reservoir(0.5));
+        let mut landscape = Landscape::create_with_topology(vec![0.0, 0.0, 0.0], line).unwrap();
+        let prec = landscape.precision();
+        let result = landscape.rain_uniform(RAIN_DENSITY, true).unwrap();
+        compare(&prec, result, &[0.5, 0.5, 0.5]);
+        assert!((landscape.outflow() - 1.5).abs() <= prec * 3.0 * 3.0);
+    }
+
+    #[test]
+    fn sv_case_drain() {
+        let mut landscape = Landscape::create(vec![5.0, 1.0, 5.0]).unwrap();
+        landscape.set_drain(1);
+        let prec = landscape.precision();
+        let result = landscape.rain_uniform(RAIN_DENSITY, true).unwrap();
+        compare(&prec, result, &[5.0, 1.0, 5.0]);
+        assert!((landscape.outflow() - 3.0).abs() <= prec * 3.0);
+    }
+
+    #[test]
+    fn sv_case_areas() {
+        let mut landscape = Landscape::create(vec![0.0, 0.0, 2.0]).unwrap();
+        landscape.set_areas(vec![1.0, 3.0, 2.0]).unwrap();
+        let prec = landscape.precision();
+        let result = landscape.rain(|i| if i == 0 { 4.0 } else { 0.0 }, true).unwrap();
+        compare(&prec, result, &[1.0, 1.0, 2.0]);
+        assert!(landscape.set_flow_mode(crate::flow::FlowMode::Linear(1.0)).is_err());
+    }
+
+    #[test]
+    fn sv_case_fewer_iterations() {
+        use crate::simul_manual_1th_v2;
+        use crate::simul_manual_1th_v3;
+        // Slope with steps: water falling onto the top runs down to the lowest point within one iteration.
+        let points: Vec<f64> = (0..200).map(|i| ((200 - i) / 4) as f64).collect();
+        let mut landscape = Landscape::create(points.clone()).unwrap();
+        let mut v2 = simul_manual_1th_v2::Landscape::create(points.clone()).unwrap();
+        let mut v3 = simul_manual_1th_v3::Landscape::create(points).unwrap();
+        for _ in 0..3 {
+            let expected = v3.rain_uniform(RAIN_DENSITY, true).unwrap().to_vec();
+            v2.rain_uniform(RAIN_DENSITY, false).unwrap();
+            let prec = landscape.precision();
+            compare(&prec, landscape.rain_uniform(RAIN_DENSITY, true).unwrap(), &expected);
+            assert!(landscape.stats().iterations < v2.stats().iterations);
+        }
+    }
+}
@@ -5,306 +5,148 @@
 
 use std::iter::FromIterator;
 
-use anyhow::Result;
+use crate::iterative::{IterativeLandscape, Model, Scheduler, WaterUpdate};
+use crate::topology::{Line1D, Topology};
 
-#[cfg(feature = "state_fun_bd")]
-#[allow(unused_imports)]
-use bigdecimal::{BigDecimal, Zero};
-
-// use crate::PointHeight;
-/// Base unclehood type used for calculations during simulation in this module.
-type PointHeight = <Landscape as crate::Solver>::PointHeight;
-
-/// If water level is less than this value water does not flow from point to point.
-/// Note: Placing 0.0 here may cause program to fall into infinite loop because of rounding errors.
-const VISCOSITY_COEF: PointHeight = 0.01;
-
-// TODO: Point1D could be made a template parameter, with some generic trait implementation.
-// The same algorithm may work for other topologies (e.g. Point2D, generic Point with its own list of neighbors, etc.`)
 /// Represents entire 'world' where water is raining onto and flowing down from point (section in the paper) to point.
-pub struct Landscape {
-    points: Vec<Point>,
-    points_idx: Vec<usize>,
-    results: Vec<PointHeight>,
-    precision: PointHeight,
-}
-
-#[derive(Debug)]
-struct WaterUpdate {
-    from_idx: usize,
-    to_idx: usize,
-    water: PointHeight,
+pub type Landscape<T = Line1D> = IterativeLandscape<GroundOrder, T>;
 
-    #[cfg(any(feature = "state_fun_f64", feature = "state_fun_bd"))]
-    from: Point,
-    #[cfg(any(feature = "state_fun_f64", feature = "state_fun_bd"))]
-    to: Point,
+/// Evaluates all points from highest ground to lowest, flows are applied when all points are evaluated.
+pub struct GroundOrder {
+    points_idx: Vec<usize>,
+    send_water_to: Vec<usize>, // TODO: possibly use smallvec or tiny_vec
 }
 
-impl Landscape {
-    /// Create Landscape object.
-    /// `points` object is intentionally consumed to free memory as soon as possible.
-    /// In this case however it is moved and re-used as buffer for results.
-    #[allow(dead_code)]
-    pub fn create(ph: Vec<f64>) -> Self {
-        let mut points = Vec::with_capacity(ph.len());
-        for h in &ph {
-            points.push(Point::with_height((*h).into()));
-        }
-        let mut points_idx = Vec::from_iter((0..ph.len()).into_iter());
-        points_idx.sort_unstable_by(|i, j| ph[*j].partial_cmp(&ph[*i]).unwrap());
-        Landscape { points, points_idx, results:ph, precision:VISCOSITY_COEF }
+impl<T: Topology> Scheduler<T> for GroundOrder {
+    fn new(model: &Model<T>) -> Self {
+        let points = &model.points;
+        let mut points_idx = Vec::from_iter(0..points.len());
+        points_idx.sort_unstable_by(|i, j| points[*j].ground.total_cmp(&points[*i].ground));
+        GroundOrder { points_idx, send_water_to: Vec::new() }
     }
 
-    /// Create Landscape object.
-    /// `points` object is intentionally consumed to free memory as soon as possible.
-    /// `precision` precision in which to perform simulation, the less the worse performance.
-    /// Warning: setting precision equal to zero may cause simulation hang.
-    #[allow(dead_code)]
-    pub fn create_with_precision(ph: Vec<f64>, precision: PointHeight) -> Self {
-        let mut landscape = Self::create(ph);
-        landscape.precision = precision;
-        landscape
-    }
-
-    // TODO: This could be implemented as different specializations for different points passed as template parameter
-    /// Determines directions in which water can flow from point at `idx` index.
-    fn neighbors(&self, idx: usize) -> impl Iterator<Item=usize> {
-        Iter1D {idx, max:self.points.len(), iter:0}
-    }
-
-    /// Function that determines how water is flowing thru landscape.
-    /// Please look at `README.md` for more information.
-    fn stabilize_water(&mut self) -> Result<()> {
-        #[cfg(any(feature = "state_fun_f64", feature = "state_fun_bd"))]
-        let (state_lbound, mut state) = (self.calc_state_lbound(), self.calc_state());
-
-        let mut send_water_to = Vec::new(); // TODO: possibly use smallvec or tiny_vec
-        let mut water_update = Vec::new();
-        loop {
-            water_update.clear();
-            for pi in &self.points_idx {
-                let pw = self.points[*pi].water;
-                if pw <= self.precision {
-                    continue;
-                }
-                send_water_to.clear(); 
-                let ph = self.points[*pi].get_height();
-                for ni in self.neighbors(*pi) {
-                    let nh = self.points[ni].get_height();
-                    if ph > nh + self.precision {
-                        send_water_to.push(ni);
-                    }
-                }
-                if send_water_to.is_empty() {
-                    continue;
-                }
-                let equal_fraction = pw / send_water_to.len() as PointHeight;
-                for ni in &send_water_to {
-                    let diff = self.points[*pi].get_height() - self.points[*ni].get_height();
-                    if diff > self.precision {
-                        let flow_amt = if equal_fraction < diff / 2.0 { equal_fraction } else { diff / 2.0 };
-                        water_update.push(
-                            WaterUpdate {
-                                from_idx: *pi,
-                                to_idx: *ni,
-                                water: flow_amt,
-                                
-                                #[cfg(any(feature = "state_fun_f64", feature = "state_fun_bd"))]
-                                from: self.points[*pi].clone(),
-                                #[cfg(any(feature = "state_fun_f64", feature = "state_fun_bd"))]
-                                to: self.points[*ni].clone(),
-                            }
-                        );
-                    }
-                }
-            }
-            if water_update.is_empty() {
-                break;
-            }
-            for wu in &mut water_update {
-                self.points[wu.from_idx].water -= wu.water;
-                self.points[wu.to_idx].water += wu.water;
-            }
-            
-            #[cfg(any(feature = "state_fun_f64", feature = "state_fun_bd"))] {
-            let new_state = self.calc_state();
-                if state < state_lbound {
-                    dbg!(&water_update);
-                    bail!("State function check failed: state ({}) < low bound ({})", state, state_lbound);
-                }
-                if new_state < state_lbound {
-                    dbg!(&water_update);
-                    bail!("State function check failed: new_state ({}) < low bound ({})", new_state, state_lbound);
-                }
-                if new_state > state {
-                    dbg!(&water_update);
-                    bail!("State function check failed: new_state ({}) > prev_state ({})", new_state, state);
-                }
-                if new_state == state {
-                    dbg!(&water_update);
-                    bail!("State function check failed: new_state ({}) == prev_state ({}); Function should return before", new_state, state);
-                }
-                // dbg!(&state_lbound, &new_state, &state);
-                state = new_state;
-            }
+    fn iteration(&mut self, model: &mut Model<T>, dt: f64, water_update: &mut Vec<WaterUpdate>) {
+        model.stats.visits += model.points.len();
+        for pi in &self.points_idx {
+            model.calc_flows(*pi, dt, &mut self.send_water_to, water_update);
         }
-        Ok(())
-    }
-    
-    #[cfg(feature = "state_fun_f64")]
-    fn calc_state(&self) -> f64 {
-        let mut state = 0.0;
-        for p in &self.points {
-            state += (p.get_height() as f64).powf(1.4);
+        for wu in water_update.iter() {
+            model.apply(wu);
         }
-        state        
-    }
-
-    #[cfg(feature = "state_fun_f64")]
-    fn calc_state_lbound(&self) -> f64 {
-        let mut lbound = 0.0;
-        for p in &self.points {
-            lbound += (p.ground as f64).powf(1.4);
-        }
-        lbound        
-    }
-
-    /* Note: I created those versions while debugging failure on calc_state checks.
-       But it turned out to be caused by PointHeight being f32 instead of f64.
-       In practice this versions seems to be useless because of huge performance degradation in compare to f64.
-       But left them in place just in case.
-     */
-    #[cfg(all(feature = "state_fun_bd", not(feature = "state_fun_f64")))]
-    fn calc_state(&self) -> BigDecimal {
-        let mut state = Zero::zero();
-        for p in &self.points {
-            state += BigDecimal::from(p.get_height()).square();
-        }
-        state        
-    }
-
-    #[cfg(all(feature = "state_fun_bd", not(feature = "state_fun_f64")))]
-    fn calc_state_lbound(&self) -> BigDecimal {
-        let mut lbound = Zero::zero();
-        for p in &self.points {
-            lbound += BigDecimal::from(p.ground).square();
-        }
-        lbound        
     }
 }
 
-impl crate::Solver for Landscape {
-    /// Base unclehood type used for calculations during simulation in this module.
-    type PointHeight = f64; 
-    
-    /// Simulates one step of falling rain.
-    fn rain(&mut self, rain_distr: impl Fn(usize) -> PointHeight, return_result: bool) -> Result<&[PointHeight]> {
-        for (idx, p) in self.points.iter_mut().enumerate() {
-            p.rain(rain_distr(idx));
-        }
-
-        self.stabilize_water()?;
+///////////////////////////////////////////////////////////////////////////////////////////////////
+// Tests
+//
 
-        if return_result {
-            for (i, p) in self.points.iter().enumerate() {
-                self.results[i] = p.get_height();
-            }
-            Ok(&self.results[..])
-        } else {
-            Ok(&[])
-        }
-    }
-    
-    /// Returns simulation precision.
-    fn precision(&self) -> PointHeight { self.precision }
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::*;
+    use crate::iterative::PointHeight;
+    const STRICT_EQUALITY:bool = false;
 
-/// Represents point (section) on landscape
-#[derive(Debug, Clone)]
-struct Point {
-    ground: PointHeight,
-    water: PointHeight,
-}
+    include!("test_common_f64.inc.rs");
 
-impl Point {
-    /// Point constructor
-    #[allow(dead_code)]
-    fn with_height(h: PointHeight) -> Self {
-        Point { 
-            ground: h,
-            water: 0.0,
-        }
+    #[test]
+    fn sv_case_boundaries() {
+        use crate::topology::Boundary;
+        let mut line = Line1D::new(2);
+        line.set_boundaries(Boundary::Wall, Boundary::Outflow);
+        let mut landscape = Landscape::create_with_topology(vec![1.0, 2.0], line).unwrap();
+        let prec = landscape.precision();
+        let result = landscape.rain_uniform(RAIN_DENSITY, true).unwrap();
+        compare(&prec, result, &[2.0, 2.0]);
+        assert!((landscape.outflow() - 1.0).abs() <= prec * 2.0);
+
+        let mut line = Line1D::new(3);
+        line.set_boundaries(Boundary::Wall, Boundary::Reservoir(0.5));
+        let mut landscape = Landscape::create_with_topology(vec![0.0, 0.0, 0.0], line).unwrap();
+        let result = landscape.rain_uniform(RAIN_DENSITY, true).unwrap();
+        compare(&prec, result, &[0.5, 0.5, 0.5]);
+        // Level of every point may differ from the exact one by up to `prec * len`.
+        assert!((landscape.outflow() - 1.5).abs() <= prec * 3.0 * 3.0);
     }
 
-    /// Returns level of water (dry point height + water over it)
-    #[inline]
-    fn get_height(&self) -> PointHeight {
-        self.ground + self.water
-    }
-    
-    /// Simulate `cnt` amount of water raining on point
-    #[inline]
-    fn rain(&mut self, cnt: PointHeight) {
-        self.water += cnt;
+    #[test]
+    fn sv_case_drain() {
+        let mut landscape = Landscape::create(vec![5.0, 1.0, 5.0]).unwrap();
+        landscape.set_drain(1);
+        let prec = landscape.precision();
+        let result = landscape.rain_uniform(RAIN_DENSITY, true).unwrap();
+        compare(&prec, result, &[5.0, 1.0, 5.0]);
+        assert!((landscape.outflow() - 3.0).abs() <= prec * 3.0);
     }
-}
 
-struct Iter1D {
-    idx: usize,
-    max: usize,
-    iter: u8,
-}
-
-impl Iterator for Iter1D {
-    type Item = usize;
-    fn next(&mut self) -> Option<Self::Item> {
-        match self.iter {
-            0 => {
-                self.iter = 1;
-                if self.idx > 0 { Some(self.idx - 1) } else { if self.max > 1 { Some(1) } else { None }}
-            },
-            1 => {
-                self.iter = 2;
-                if self.idx == 0 { None } else { if self.idx < self.max - 1 { Some(self.idx + 1) } else { None }}
-            },
-            _ => None
+    #[test]
+    fn sv_case_flow_mode() {
+        use crate::flow::FlowMode;
+        let mut landscape = Landscape::create(vec![0.0, 0.0, 0.0]).unwrap();
+        landscape.set_flow_mode(FlowMode::Linear(0.25)).unwrap();
+        let prec = landscape.precision();
+        // Water flows only part of the way in one step.
+        let result = landscape.rain(|i| if i == 0 { 3.0 } else { 0.0 }, true).unwrap();
+        assert!(result[0] > 1.5 && result[0] > result[1] && result[1] > result[2]);
+        assert!((result.iter().sum::<f64>() - 3.0).abs() <= prec);
+        // After enough dry steps levels equalize.
+        for _ in 0..100 {
+            landscape.rain_uniform(0.0, false).unwrap();
         }
+        let result = landscape.rain_uniform(0.0, true).unwrap();
+        compare(&prec, result, &[1.0, 1.0, 1.0]);
     }
-}
-
-/* 
-///Potentially to build more complicated topology
-#[derive(Debug, Clone)]
-struct PointFreehand {
-    ground: PointHeight,
-    neighbors: Vec<usize>,
-}
 
-impl PointFreehand {
-    fn with_height(h: PointHeight) -> Self {
-        PointFreehand { 
-            ground: h,
-            neighbors: Vec::with_capacity(2) 
-        }
+    #[test]
+    fn sv_case_areas() {
+        let mut landscape = Landscape::create(vec![0.0, 0.0, 2.0]).unwrap();
+        landscape.set_areas(vec![1.0, 3.0, 2.0]).unwrap();
+        let prec = landscape.precision();
+        // Volume of 4 falling onto first point is spread over area of 4.
+        let result = landscape.rain(|i| if i == 0 { 4.0 } else { 0.0 }, true).unwrap();
+        compare(&prec, result, &[1.0, 1.0, 2.0]);
+        // Rain volume is scaled by area: total volume 4 + 6 fills all points up to (10 + 2 * 2) / 6
+        let result = landscape.rain_uniform(RAIN_DENSITY, true).unwrap();
+        compare(&prec, result, &[7.0 / 3.0, 7.0 / 3.0, 7.0 / 3.0]);
+        assert!(landscape.set_areas(vec![1.0, 0.0, 1.0]).is_err());
     }
-    
-    #[inline]
-    fn add_neighbor(&mut self, other: usize) {
-        self.neighbors.push(other);
-    }
-}
-*/
 
-///////////////////////////////////////////////////////////////////////////////////////////////////
-/// Tests
-/// 
+    #[test]
+    fn sv_case_stats() {
+        let mut landscape = Landscape::create(vec![3.0, 1.0, 6.0, 4.0, 8.0, 9.0]).unwrap();
+        landscape.rain_uniform(RAIN_DENSITY, false).unwrap();
+        let stats = landscape.stats();
+        assert!(stats.iterations > 1 && stats.updates > 0 && stats.volume_moved > 0.0);
+        // Water on flat landscape does not flow - only one iteration is needed to find it out.
+        let mut landscape = Landscape::create(vec![1.0, 1.0]).unwrap();
+        landscape.rain_uniform(RAIN_DENSITY, false).unwrap();
+        assert_eq!((landscape.stats().iterations, landscape.stats().updates), (1, 0));
+    }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use super::super::*;
-    const STRICT_EQUALITY:bool = false;
+    #[test]
+    fn sv_case_verify_grid_pit() {
+        use crate::topology::{Grid2D, Neighborhood};
+        // All points around the lowest one flow into it at once, which must not raise it above them.
+        let mut landscape = Landscape::create_with_topology(vec![3.0, 3.0, 3.0, 3.0, 0.0, 3.0, 3.0, 3.0, 3.0], Grid2D::new(3, 3, Neighborhood::Moore)).unwrap();
+        landscape.set_verify(true).unwrap();
+        let prec = landscape.precision();
+        let result = landscape.rain_uniform(RAIN_DENSITY, true).unwrap();
+        compare(&prec, result, &[11.0 / 3.0; 9]);
+    }
 
-    include!("test_common_f64.inc.rs");
+    #[test]
+    fn sv_case_verify_negative_rain() {
+        use crate::error::Error;
+        use crate::verify::Violation;
+        // Water does not flow on flat landscape, so negative water is found only by the final check.
+        let mut landscape = Landscape::create(vec![2.0, 2.0, 2.0]).unwrap();
+        landscape.set_verify(true).unwrap();
+        let report = match landscape.rain_uniform(-RAIN_DENSITY, false) {
+            Err(Error::Verification(report)) => report,
+            other => panic!("expected verification error, got {:?}", other),
+        };
+        assert_eq!(report.violations.len(), 3);
+        assert!(report.violations.iter().all(|v| matches!(v, Violation::NegativeWater { .. })));
+        assert_eq!(report.points(), vec![0, 1, 2]);
+    }
 }
@@ -0,0 +1,500 @@
+/*!
+ * Solve problem using manually written simulation working in main thread.
+ * Version 3: Exact simulation - connected points with equal water level are treated as one pool,
+ * water is routed downhill and pools are filled (and merged when they spill) analytically.
+ */
+
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+use crate::error::{Error, Result};
+use crate::check_landscape;
+
+use crate::losses::Losses;
+use crate::topology::{Boundary, Line1D, Topology};
+use crate::verify::{Report, Violation, VOLUME_TOLERANCE};
+
+/// Base unclehood type used for calculations during simulation in this module.
+type PointHeight = <Landscape as crate::Solver>::PointHeight;
+
+/// Represents entire 'world' where water is raining onto and flowing down from point (section in the paper) to point.
+pub struct Landscape<T: Topology = Line1D> {
+    topology: T,
+    points: Vec<Point>,
+    results: Vec<PointHeight>,
+    /// Water which has arrived at point (rain or inflow from higher points) but was not routed yet.
+    pending: Vec<PointHeight>,
+    /// Marks members of currently processed pool (buffer re-used between calls).
+    in_pool: Vec<bool>,
+    /// Conditions at the edge of landscape (or drains) for every point.
+    outlets: Vec<Boundary<PointHeight>>,
+    /// Amount of rain added to landscape during last `rain()` call.
+    rain_added: PointHeight,
+    /// Amount of water which left landscape during last `rain()` call.
+    outflow: PointHeight,
+    /// Infiltration and evaporation (if enabled).
+    losses: Option<Losses>,
+    /// Amount of water lost during last `rain()` call.
+    loss: PointHeight,
+    /// Simulated time (in hours) since landscape was created.
+    time: f64,
+    /// Checks invariants of simulation (see `verify` module).
+    verify: bool,
+}
+
+/// Entry of the queue of points with pending water, ordered by water level (highest first).
+#[derive(Debug)]
+struct PendingWater {
+    level: PointHeight,
+    idx: usize,
+}
+
+impl PartialEq for PendingWater {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for PendingWater {}
+
+impl PartialOrd for PendingWater {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for PendingWater {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // Lower index first on equal level, so that results do not depend on heap internals.
+        self.level.total_cmp(&other.level).then_with(|| other.idx.cmp(&self.idx))
+    }
+}
+
+impl Landscape {
+    /// Create Landscape object with points placed on the line (as in problem description).
+    /// `points` object is intentionally consumed to free memory as soon as possible.
+    /// In this case however it is moved and re-used as buffer for results.
+    #[allow(dead_code)]
+    pub fn create(ph: Vec<f64>) -> Result<Self> {
+        let topology = Line1D::new(ph.len());
+        Self::create_with_topology(ph, topology)
+    }
+
+    /// Create Landscape object.
+    /// Simulation in this module is exact, so `precision` is ignored.
+    /// Function is provided only for compatibility with other modules.
+    #[allow(dead_code)]
+    pub fn create_with_precision(ph: Vec<f64>, _precision: PointHeight) -> Result<Self> {
+        Self::create(ph)
+    }
+}
+
+impl<T: Topology> Landscape<T> {
+    /// Create Landscape object with points connected according to given `topology`.
+    /// `ph` must contain finite (possibly negative) height of every point of topology,
+    /// otherwise `Error::SizeMismatch` or `Error::NonFiniteHeight` is returned.
+    #[allow(dead_code)]
+    pub fn create_with_topology(ph: Vec<f64>, topology: T) -> Result<Self> {
+        check_landscape(&ph, topology.len())?;
+        let mut points = Vec::with_capacity(ph.len());
+        for h in &ph {
+            points.push(Point::with_height(*h));
+        }
+        let pending = vec![0.0; ph.len()];
+        let in_pool = vec![false; ph.len()];
+        let outlets = (0..ph.len()).map(|i| topology.boundary(i)).collect();
+        Ok(Landscape { topology, points, results:ph, pending, in_pool, outlets, rain_added:0.0, outflow:0.0, losses:None, loss:0.0, time:0.0, verify:false })
+    }
+
+    /// Create Landscape object with points connected according to given `topology`.
+    /// Simulation in this module is exact, so `precision` is ignored.
+    #[allow(dead_code)]
+    pub fn create_with_topology_and_precision(ph: Vec<f64>, topology: T, _precision: PointHeight) -> Result<Self> {
+        Self::create_with_topology(ph, topology)
+    }
+
+    /// Marks point at `idx` index as drain - water flows from it outside of landscape (as to infinitely low neighbor).
+    #[allow(dead_code)]
+    pub fn set_drain(&mut self, idx: usize) {
+        self.outlets[idx] = Boundary::Outflow;
+    }
+
+    /// Determines directions in which water can flow from point at `idx` index.
+    fn neighbors(&self, idx: usize) -> T::Neighbors<'_> {
+        self.topology.neighbors(idx)
+    }
+
+    /// Fills `pool` with indexes of points connected to `idx` which have the same water level.
+    /// Members of pool are marked in `self.in_pool`.
+    fn collect_pool(&mut self, idx: usize, pool: &mut Vec<usize>) {
+        pool.clear();
+        pool.push(idx);
+        self.in_pool[idx] = true;
+        let level = self.points[idx].get_height();
+        let mut i = 0;
+        while i < pool.len() {
+            for ni in self.topology.neighbors(pool[i]) {
+                if !self.in_pool[ni] && self.points[ni].get_height() == level {
+                    self.in_pool[ni] = true;
+                    pool.push(ni);
+                }
+            }
+            i += 1;
+        }
+    }
+
+    /// Function that determines how water is flowing thru landscape.
+    /// Water pending on points is processed from the highest water level to the lowest one.
+    /// Pool which has lower neighbors (outlets) passes all its pending water to them in equal parts,
+    /// otherwise it is filled up - at most to the level of its lowest neighbor, which joins the pool then.
+    /// Open boundaries (and drains) of pool members are outlets too, reservoir only when pool reaches its level.
+    /// Please look at `README.md` for more information.
+    fn stabilize_water(&mut self) -> Result<()> {
+        // Volume of water is calculated only in verification mode.
+        let expected_volume = if self.verify { self.calc_volume() + self.outflow } else { 0.0 };
+
+        let mut queue = BinaryHeap::new();
+        for (idx, pw) in self.pending.iter().enumerate() {
+            if *pw > 0.0 {
+                queue.push(PendingWater { level: self.points[idx].get_height(), idx });
+            }
+        }
+
+        let mut pool = Vec::new();
+        let mut outlets = Vec::new();
+        while let Some(PendingWater { level, idx }) = queue.pop() {
+            if self.pending[idx] <= 0.0 || self.points[idx].get_height() != level {
+                // Pending water was already collected by pool processed before, or the entry is outdated.
+                continue;
+            }
+            self.collect_pool(idx, &mut pool);
+
+            let mut volume = 0.0;
+            outlets.clear();
+            let mut rim = f64::INFINITY;
+            let mut sinks = 0;
+            for pi in &pool {
+                volume += self.pending[*pi];
+                self.pending[*pi] = 0.0;
+                match self.outlets[*pi] {
+                    Boundary::Wall => {},
+                    Boundary::Outflow => sinks += 1,
+                    Boundary::Reservoir(rl) if rl <= level => sinks += 1,
+                    Boundary::Reservoir(rl) => if rl < rim {
+                        rim = rl;
+                    },
+                }
+                for ni in self.neighbors(*pi) {
+                    if self.in_pool[ni] {
+                        continue;
+                    }
+                    let nh = self.points[ni].get_height();
+                    if nh < level {
+                        outlets.push(ni);
+                    } else if nh < rim {
+                        rim = nh;
+                    }
+                }
+            }
+            for pi in &pool {
+                self.in_pool[*pi] = false;
+            }
+            // Outlet adjacent to several members of pool gets its part only once.
+            outlets.sort_unstable();
+            outlets.dedup();
+
+            if !outlets.is_empty() || sinks > 0 {
+                let equal_fraction = volume / (outlets.len() + sinks) as PointHeight;
+                for ni in &outlets {
+                    self.pending[*ni] += equal_fraction;
+                    queue.push(PendingWater { level: self.points[*ni].get_height(), idx: *ni });
+                }
+                self.outflow += equal_fraction * sinks as PointHeight;
+                continue;
+            }
+
+            let area = pool.len() as PointHeight;
+            let new_level = level + volume / area;
+            if new_level < rim {
+                for pi in &pool {
+                    self.points[*pi].level = new_level;
+                }
+            } else {
+                // Pool reaches its rim: it merges with the lowest neighbor (or spills into reservoir) and the rest of water is processed again.
+                for pi in &pool {
+                    self.points[*pi].level = rim;
+                }
+                let rest = volume - (rim - level) * area;
+                if rest > 0.0 {
+                    self.pending[idx] = rest;
+                    queue.push(PendingWater { level: rim, idx });
+                }
+            }
+        }
+        if self.verify {
+            self.check_state(expected_volume)?;
+        }
+        Ok(())
+    }
+
+    /// Checks that volume of water with outflow is equal to `expected_volume` (within rounding errors)
+    /// and that no water can flow anymore (verification mode).
+    fn check_state(&self, expected_volume: PointHeight) -> Result<()> {
+        let mut report = Report::new(self.time, 0);
+        let volume = self.calc_volume() + self.outflow;
+        if (volume - expected_volume).abs() > VOLUME_TOLERANCE * expected_volume.abs().max(1.0) {
+            report.violations.push(Violation::MassNotConserved { expected: expected_volume, actual: volume });
+        }
+        for (pi, p) in self.points.iter().enumerate() {
+            if p.level < p.ground {
+                report.violations.push(Violation::NegativeWater { point: pi, water: p.get_water() });
+            }
+            if p.level == p.ground {
+                continue;
+            }
+            for ni in self.neighbors(pi) {
+                if self.points[ni].level < p.level {
+                    report.violations.push(Violation::UnstableWater { point: pi, level: p.level, neighbor: ni, neighbor_level: self.points[ni].level });
+                }
+            }
+        }
+        report.into_result()
+    }
+
+    /// Returns total amount of water stored in landscape (pending water included).
+    fn calc_volume(&self) -> PointHeight {
+        self.points.iter().map(|p| p.get_water()).sum::<PointHeight>() + self.pending.iter().sum::<PointHeight>()
+    }
+}
+
+impl<T: Topology> crate::Solver for Landscape<T> {
+    /// Base unclehood type used for calculations during simulation in this module.
+    type PointHeight = f64;
+
+    /// Simulates `dt` hours of falling rain.
+    fn rain_for(&mut self, dt: f64, rain_distr: impl Fn(usize) -> PointHeight + Sync, return_result: bool) -> Result<&[PointHeight]> {
+        self.rain_added = 0.0;
+        self.outflow = 0.0;
+        self.loss = 0.0;
+        for (idx, pw) in self.pending.iter_mut().enumerate() {
+            let rain = rain_distr(idx) * dt;
+            *pw += rain;
+            self.rain_added += rain;
+        }
+        if let Some(losses) = &mut self.losses {
+            // Losses differ from point to point, so pools would not be level anymore:
+            // water standing on points is released as pending and routed again.
+            for (idx, p) in self.points.iter_mut().enumerate() {
+                let water = self.pending[idx] + p.get_water();
+                if water > 0.0 {
+                    let lost = losses.apply(idx, water, dt);
+                    self.loss += lost;
+                    self.pending[idx] = water - lost;
+                    p.level = p.ground;
+                }
+            }
+        }
+
+        self.stabilize_water()?;
+        self.time += dt;
+
+        if return_result {
+            for (i, p) in self.points.iter().enumerate() {
+                self.results[i] = p.get_height();
+            }
+            Ok(&self.results[..])
+        } else {
+            Ok(&[])
+        }
+    }
+
+    /// Returns simulation precision (0 - simulation is exact).
+    fn precision(&self) -> PointHeight { 0.0 }
+
+    /// Returns amount of water which left landscape during last `rain()` call.
+    fn outflow(&self) -> PointHeight { self.outflow }
+
+    /// Returns simulated time (in hours).
+    fn time(&self) -> f64 { self.time }
+
+    /// Returns amount of rain added to landscape during last `rain()` call.
+    fn rain_added(&self) -> PointHeight { self.rain_added }
+
+    /// Returns amount of water stored in landscape.
+    fn stored(&self) -> PointHeight {
+        self.calc_volume()
+    }
+
+    /// Enables checking of invariants of simulation.
+    fn set_verify(&mut self, verify: bool) -> Result<()> {
+        self.verify = verify;
+        Ok(())
+    }
+
+    /// Enables infiltration and evaporation.
+    fn set_losses(&mut self, losses: Losses) -> Result<()> {
+        if losses.len() != self.points.len() {
+            return Err(Error::SizeMismatch { what: "Losses".into(), len: losses.len(), expected: self.points.len() });
+        }
+        self.losses = Some(losses);
+        Ok(())
+    }
+
+    /// Returns amount of water lost during last `rain()` call.
+    fn loss(&self) -> PointHeight { self.loss }
+}
+
+/// Represents point (section) on landscape.
+/// Unlike in other modules water level is stored (not water amount), so that all points of pool have exactly the same level.
+#[derive(Debug, Clone)]
+struct Point {
+    ground: PointHeight,
+    level: PointHeight,
+}
+
+impl Point {
+    /// Point constructor
+    fn with_height(h: PointHeight) -> Self {
+        Point {
+            ground: h,
+            level: h,
+        }
+    }
+
+    /// Returns level of water (dry point height + water over it)
+    #[inline]
+    fn get_height(&self) -> PointHeight {
+        self.level
+    }
+
+    /// Returns amount of water over the point
+    #[inline]
+    fn get_water(&self) -> PointHeight {
+        self.level - self.ground
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////////////////////////
+// Tests
+//
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::*;
+    const STRICT_EQUALITY:bool = true;
+
+    include!("test_common_f64.inc.rs");
+
+    #[test]
+    fn sv_case_readme_4111() {
+        let points = vec![4.0, 1.0, 1.0, 1.0];
+        let mut landscape = Landscape::create(points).unwrap();
+        let prec = landscape.precision();
+        let result = landscape.rain_uniform(RAIN_DENSITY, true).unwrap();
+        let level = 1.0 + 4.0 / 3.0;
+        compare(&prec, result, &[4.0, level, level, level]);
+    }
+
+    #[test]
+    fn sv_case_mail2_merge() {
+        let points = vec![8.0, 8.0, 1.0];
+        let mut landscape = Landscape::create(points).unwrap();
+        let prec = landscape.precision();
+        let result = landscape.rain_uniform(RAIN_DENSITY, true).unwrap();
+        compare(&prec, result, &[8.0, 8.0, 4.0]);
+        let result = landscape.rain_uniform(RAIN_DENSITY, true).unwrap();
+        compare(&prec, result, &[8.0, 8.0, 7.0]);
+        let result = landscape.rain_uniform(RAIN_DENSITY, true).unwrap();
+        let level = 8.0 + 2.0 / 3.0;
+        compare(&prec, result, &[level, level, level]);
+    }
+
+    #[test]
+    fn sv_case_sample_steps() {
+        let points = vec![3.0, 1.0, 6.0, 4.0, 8.0, 9.0];
+        let mut landscape = Landscape::create(points).unwrap();
+        let prec = landscape.precision();
+        landscape.rain_uniform(RAIN_DENSITY, false).unwrap();
+        let result = landscape.rain_uniform(RAIN_DENSITY, true).unwrap();
+        compare(&prec, result, &[6.5, 6.5, 6.5, 6.5, 8.0, 9.0]);
+    }
+
+    #[test]
+    fn sv_case_grid_neighborhood() {
+        use crate::topology::{Grid2D, Neighborhood};
+        let points = vec![3.0, 9.0, 9.0, 1.0];
+        let mut landscape = Landscape::create_with_topology(points.clone(), Grid2D::new(2, 2, Neighborhood::VonNeumann)).unwrap();
+        let prec = landscape.precision();
+        let result = landscape.rain_uniform(RAIN_DENSITY, true).unwrap();
+        compare(&prec, result, &[5.0, 9.0, 9.0, 3.0]);
+
+        // Water from upper left corner flows diagonally to lower right one.
+        let mut landscape = Landscape::create_with_topology(points, Grid2D::new(2, 2, Neighborhood::Moore)).unwrap();
+        let result = landscape.rain_uniform(RAIN_DENSITY, true).unwrap();
+        compare(&prec, result, &[4.0, 9.0, 9.0, 4.0]);
+    }
+
+    #[test]
+    fn sv_case_shared_outlet() {
+        use crate::topology::{Grid2D, Neighborhood};
+        // Pool of points 1 and 2 has two outlets: point 0 and point 6, which is adjacent to both members of pool,
+        // but gets the same part of water as point 0.
+        let points = vec![0.0, 5.0, 5.0, 9.0, 9.0, 9.0, 0.0, 9.0];
+        let mut landscape = Landscape::create_with_topology(points, Grid2D::new(2, 4, Neighborhood::Moore)).unwrap();
+        let prec = landscape.precision();
+        let result = landscape.rain(|i| if i == 1 || i == 2 { 1.0 } else { 0.0 }, true).unwrap();
+        compare(&prec, result, &[1.0, 5.0, 5.0, 9.0, 9.0, 9.0, 1.0, 9.0]);
+    }
+
+    #[test]
+    fn sv_case_graph_tunnel() {
+        use crate::topology::Graph;
+        let points = vec![5.0, 9.0, 1.0];
+        let mut graph = Graph::new(3);
+        graph.add_edge(0, 1);
+        graph.add_edge(1, 2);
+        let mut landscape = Landscape::create_with_topology(points.clone(), graph.clone()).unwrap();
+        let prec = landscape.precision();
+        let result = landscape.rain_uniform(RAIN_DENSITY, true).unwrap();
+        compare(&prec, result, &[6.5, 9.0, 2.5]);
+
+        // Tunnel between not adjacent points 0 and 2.
+        graph.add_edge(0, 2);
+        let mut landscape = Landscape::create_with_topology(points, graph).unwrap();
+        let result = landscape.rain_uniform(RAIN_DENSITY, true).unwrap();
+        compare(&prec, result, &[5.0, 9.0, 4.0]);
+    }
+
+    #[test]
+    fn sv_case_boundaries() {
+        use crate::topology::Boundary;
+        let mut line = Line1D::new(2);
+        line.set_boundaries(Boundary::Wall, Boundary::Outflow);
+        let mut landscape = Landscape::create_with_topology(vec![1.0, 2.0], line).unwrap();
+        let prec = landscape.precision();
+        let result = landscape.rain_uniform(RAIN_DENSITY, true).unwrap();
+        compare(&prec, result, &[2.0, 2.0]);
+        assert_eq!(landscape.outflow(), 1.0);
+
+        let mut line = Line1D::new(3);
+        line.set_boundaries(Boundary::Wall, Boundary::Reservoir(0.5));
+        let mut landscape = Landscape::create_with_topology(vec![0.0, 0.0, 0.0], line).unwrap();
+        let result = landscape.rain_uniform(RAIN_DENSITY, true).unwrap();
+        compare(&prec, result, &[0.5, 0.5, 0.5]);
+        assert_eq!(landscape.outflow(), 1.5);
+    }
+
+    #[test]
+    fn sv_case_drain() {
+        let mut landscape = Landscape::create(vec![5.0, 1.0, 5.0, 2.0]).unwrap();
+        landscape.set_drain(1);
+        let prec = landscape.precision();
+        let result = landscape.rain_uniform(RAIN_DENSITY, true).unwrap();
+        compare(&prec, result, &[5.0, 1.0, 5.0, 3.5]);
+        assert_eq!(landscape.outflow(), 2.5);
+        let result = landscape.rain_uniform(RAIN_DENSITY, true).unwrap();
+        compare(&prec, result, &[5.0, 1.0, 5.0, 5.0]);
+        assert_eq!(landscape.outflow(), 2.5);
+    }
+}
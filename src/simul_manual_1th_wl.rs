@@ -0,0 +1,166 @@
+/*!
+ * Solve problem using manually written simulation working in main thread.  
+ * Worklist version: rules of water flow are the same as in version 2, but in every iteration only points
+ * whose level or level of their neighbor changed in previous iteration are evaluated.
+ */
+
+use std::mem;
+
+use crate::iterative::{IterativeLandscape, Model, Scheduler, WaterUpdate, OUTSIDE};
+use crate::topology::{Line1D, Topology};
+
+/// Represents entire 'world' where water is raining onto and flowing down from point (section in the paper) to point.
+pub type Landscape<T = Line1D> = IterativeLandscape<Worklist, T>;
+
+/// Evaluates only dirty points - flows are calculated as in `simul_manual_1th_v2`, but point which did not send water
+/// in previous iteration will not send it also in the next one, unless its level or level of its neighbor changes,
+/// so points touched by flows (and their neighbors) are queued for the next iteration.
+pub struct Worklist {
+    /// Points evaluated in current iteration - those whose level or level of their neighbor has changed.
+    dirty: Vec<usize>,
+    /// Points evaluated in next iteration.
+    next_dirty: Vec<usize>,
+    /// Marks points already added to `next_dirty`.
+    queued: Vec<bool>,
+    send_water_to: Vec<usize>, // TODO: possibly use smallvec or tiny_vec
+}
+
+impl Worklist {
+    /// Queues point at `idx` index (whose level has changed) and its neighbors for evaluation in next iteration.
+    fn queue_around(&mut self, topology: &impl Topology, idx: usize) {
+        for i in std::iter::once(idx).chain(topology.neighbors(idx)) {
+            if !self.queued[i] {
+                self.queued[i] = true;
+                self.next_dirty.push(i);
+            }
+        }
+    }
+}
+
+impl<T: Topology> Scheduler<T> for Worklist {
+    fn new(model: &Model<T>) -> Self {
+        Worklist { dirty: Vec::new(), next_dirty: Vec::new(), queued: vec![false; model.points.len()], send_water_to: Vec::new() }
+    }
+
+    fn start(&mut self, model: &Model<T>) {
+        // Rain changed levels of all points.
+        self.dirty.clear();
+        self.dirty.extend(0..model.points.len());
+    }
+
+    fn iteration(&mut self, model: &mut Model<T>, dt: f64, water_update: &mut Vec<WaterUpdate>) {
+        model.stats.visits += self.dirty.len();
+        for pi in &self.dirty {
+            model.calc_flows(*pi, dt, &mut self.send_water_to, water_update);
+        }
+        for wu in water_update.iter() {
+            model.apply(wu);
+            self.queue_around(&model.topology, wu.from_idx);
+            if wu.to_idx != OUTSIDE {
+                self.queue_around(&model.topology, wu.to_idx);
+            }
+        }
+        mem::swap(&mut self.dirty, &mut self.next_dirty);
+        self.next_dirty.clear();
+        for idx in &self.dirty {
+            self.queued[*idx] = false;
+        }
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////////////////////////
+// Tests
+//
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::*;
+    use crate::iterative::PointHeight;
+    use crate::simul_manual_1th_v2;
+
+    fn compare(precision: PointHeight, left: &[PointHeight], right: &[PointHeight]) {
+        assert_eq!(left.len(), right.len());
+        for (i, (l, r)) in left.iter().zip(right).enumerate() {
+            assert!((l - r).abs() <= precision * left.len() as PointHeight, "left[{}]={} != right[{}]={}", i, l, i, r);
+        }
+    }
+
+    #[test]
+    fn sv_case_sample() {
+        let mut landscape = Landscape::create(vec![3.0, 1.0, 6.0, 4.0, 8.0, 9.0]).unwrap();
+        let prec = landscape.precision();
+        let result = landscape.rain_uniform(RAIN_DENSITY, true).unwrap();
+        compare(prec, result, &[4.0, 4.0, 6.0, 6.0, 8.0, 9.0]);
+    }
+
+    #[test]
+    fn sv_case_mail() {
+        for (points, expected) in [
+            (vec![8.0, 8.0, 1.0], vec![8.0, 8.0, 4.0]),
+            (vec![1.0, 8.0, 8.0, 1.0], vec![3.0, 8.0, 8.0, 3.0]),
+            (vec![8.0, 4.0, 8.0, 8.0, 1.0], vec![8.0, 7.0, 8.0, 8.0, 3.0]),
+        ] {
+            let mut landscape = Landscape::create(points).unwrap();
+            let prec = landscape.precision();
+            compare(prec, landscape.rain_uniform(RAIN_DENSITY, true).unwrap(), &expected);
+        }
+    }
+
+    #[test]
+    fn sv_case_boundaries() {
+        use crate::topology::Boundary;
+        let mut line = Line1D::new(3);
+        line.set_boundaries(Boundary::Wall, Boundary::Reservoir(0.5));
+        let mut landscape = Landscape::create_with_topology(vec![0.0, 0.0, 0.0], line).unwrap();
+        landscape.set_drain(0);
+        let prec = landscape.precision();
+        let result = landscape.rain_uniform(RAIN_DENSITY, true).unwrap();
+        compare(prec, result, &[0.0, 0.0, 0.0]);
+        assert!((landscape.outflow() - 3.0).abs() <= prec * 3.0 * 3.0);
+    }
+
+    #[test]
+    fn sv_case_same_as_v2() {
+        // Landscape with a few deep basins, the rest is flat.
+        let points: Vec<f64> = (0..2000).map(|i| if i % 500 < 20 { (i % 500) as f64 / 2.0 } else { 10.0 }).collect();
+        let mut landscape = Landscape::create(points.clone()).unwrap();
+        landscape.set_verify(true).unwrap();
+        let mut reference = simul_manual_1th_v2::Landscape::create(points).unwrap();
+        let (mut visits, mut v2_visits) = (0, 0);
+        for _ in 0..3 {
+            let expected = reference.rain_uniform(RAIN_DENSITY, true).unwrap().to_vec();
+            let prec = landscape.precision();
+            compare(prec, landscape.rain_uniform(RAIN_DENSITY, true).unwrap(), &expected);
+            let (stats, v2_stats) = (landscape.stats(), reference.stats());
+            assert_eq!(stats.updates, v2_stats.updates);
+            visits += stats.visits;
+            v2_visits += v2_stats.visits;
+        }
+        // Water flows mostly near basins, so after first iteration of every step only few points are evaluated.
+        assert!(visits < v2_visits / 4);
+    }
+
+    #[test]
+    fn sv_case_flow_mode() {
+        use crate::flow::FlowMode;
+        let mut landscape = Landscape::create(vec![0.0, 0.0, 0.0]).unwrap();
+        landscape.set_flow_mode(FlowMode::Linear(0.25)).unwrap();
+        let prec = landscape.precision();
+        let result = landscape.rain(|i| if i == 0 { 3.0 } else { 0.0 }, true).unwrap();
+        assert!(result[0] > 1.5 && result[0] > result[1] && result[1] > result[2]);
+        for _ in 0..100 {
+            landscape.rain_uniform(0.0, false).unwrap();
+        }
+        compare(prec, landscape.rain_uniform(0.0, true).unwrap(), &[1.0, 1.0, 1.0]);
+    }
+
+    #[test]
+    fn sv_case_areas() {
+        let mut landscape = Landscape::create(vec![0.0, 0.0, 2.0]).unwrap();
+        landscape.set_areas(vec![1.0, 3.0, 2.0]).unwrap();
+        let prec = landscape.precision();
+        let result = landscape.rain(|i| if i == 0 { 4.0 } else { 0.0 }, true).unwrap();
+        compare(prec, result, &[1.0, 1.0, 2.0]);
+    }
+}
@@ -0,0 +1,277 @@
+/*!
+ * Solve problem using manually written simulation working in several threads.
+ * Version 2 with domain decomposition: landscape is divided at ridge points (the highest ones) into partitions
+ * which are processed concurrently (by `rayon` thread pool, with `parallel` feature only - otherwise landscape is not divided).
+ * Partitions are ranges of point indexes, so they follow ridges only on the line - on 2D grid they are bands of rows
+ * and on graph they depend on numbering of points (results are correct anyway, only more water flows across borders).
+ * Rules of water flow are the same as in `simul_manual_1th_v2`.
+ */
+
+use std::iter::FromIterator;
+use std::ops::Range;
+use std::thread;
+
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+use crate::iterative::{IterativeLandscape, Model, Point, PointHeight, Scheduler, WaterUpdate, OUTSIDE};
+use crate::topology::{Line1D, Topology};
+
+/// Minimal number of points in one partition - smaller landscapes are processed by less threads
+/// (cost of synchronization of threads would be higher than gain).
+const MIN_PARTITION_LEN: usize = 1024;
+
+/// Represents entire 'world' where water is raining onto and flowing down from point (section in the paper) to point.
+/// Landscape is processed by as many threads as there are available CPUs (with `parallel` feature) - see `set_threads()`.
+pub type Landscape<T = Line1D> = IterativeLandscape<Partitions, T>;
+
+/// Divides landscape into partitions processed by separate threads.
+/// Every iteration has two phases: flows are calculated from levels at the beginning of iteration
+/// (so partitions only read points of their neighbors) and then applied - each partition drains and fills its own points,
+/// and flows across borders of partitions are applied at the end.
+pub struct Partitions {
+    partitions: Vec<Partition>,
+}
+
+/// Range of points processed by one thread.
+/// Points inside partition are touched only by its thread, only flows across its borders are applied by main thread.
+#[derive(Debug, Default)]
+struct Partition {
+    range: Range<usize>,
+    /// Indexes of points of partition sorted from highest ground to lowest (as in `simul_manual_1th_v2`).
+    points_idx: Vec<usize>,
+    send_water_to: Vec<usize>,
+    /// Flows of water from points of partition calculated in current iteration.
+    water_update: Vec<WaterUpdate>,
+    /// Flows of water into points of other partitions (applied after all partitions are done).
+    border_update: Vec<WaterUpdate>,
+    /// Amount of water which left landscape from points of partition in current iteration.
+    outflow: PointHeight,
+    /// Amount of water moved by flows from points of partition in current iteration.
+    volume_moved: PointHeight,
+}
+
+impl<T: Topology> Landscape<T> {
+    /// Sets number of threads (partitions of landscape).
+    /// Partitions have at least `MIN_PARTITION_LEN` points, so small landscapes are processed by less threads.
+    /// Without `parallel` feature partitions would be processed one by one, so landscape is not divided (1 thread).
+    #[allow(dead_code)]
+    pub fn set_threads(&mut self, threads: usize) {
+        self.scheduler = Partitions::with_threads(&self.model.points, if cfg!(feature = "parallel") { threads } else { 1 });
+    }
+
+    /// Divides landscape into partitions with given (consecutive) ranges of points.
+    #[cfg(test)]
+    fn set_partitions(&mut self, ranges: Vec<Range<usize>>) {
+        self.scheduler = Partitions::with_ranges(&self.model.points, ranges);
+    }
+
+    /// Returns number of threads (partitions of landscape).
+    #[allow(dead_code)]
+    pub fn threads(&self) -> usize {
+        self.scheduler.partitions.len()
+    }
+}
+
+impl Partitions {
+    /// Divides `points` into at most `threads` partitions.
+    fn with_threads(points: &[Point], threads: usize) -> Self {
+        let ground: Vec<PointHeight> = points.iter().map(|p| p.ground).collect();
+        Self::with_ranges(points, split_at_ridges(&ground, threads))
+    }
+
+    /// Divides `points` into partitions with given (consecutive) ranges.
+    fn with_ranges(points: &[Point], ranges: Vec<Range<usize>>) -> Self {
+        let partitions = ranges.into_iter().map(|range| {
+            let mut points_idx = Vec::from_iter(range.clone());
+            points_idx.sort_unstable_by(|i, j| points[*j].ground.total_cmp(&points[*i].ground));
+            Partition { range, points_idx, ..Partition::default() }
+        }).collect();
+        Partitions { partitions }
+    }
+}
+
+impl<T: Topology> Scheduler<T> for Partitions {
+    fn new(model: &Model<T>) -> Self {
+        let threads = if cfg!(feature = "parallel") { thread::available_parallelism().map_or(1, |n| n.get()) } else { 1 };
+        Self::with_threads(&model.points, threads)
+    }
+
+    fn iteration(&mut self, model: &mut Model<T>, dt: f64, water_update: &mut Vec<WaterUpdate>) {
+        model.stats.visits += model.points.len();
+        {
+            let model = &*model;
+            for_each_partition(self.partitions.iter_mut().collect(), |part| part.calc(model, dt));
+        }
+        if self.partitions.iter().all(|part| part.water_update.is_empty()) {
+            return;
+        }
+
+        let mut chunks = Vec::with_capacity(self.partitions.len());
+        let mut rest = &mut model.points[..];
+        for part in &self.partitions {
+            let (chunk, tail) = rest.split_at_mut(part.range.len());
+            chunks.push(chunk);
+            rest = tail;
+        }
+        for_each_partition(self.partitions.iter_mut().zip(chunks).collect(), |(part, chunk)| part.apply(chunk));
+        for part in &self.partitions {
+            model.outflow += part.outflow;
+            model.stats.volume_moved += part.volume_moved;
+            for wu in &part.border_update {
+                model.points[wu.to_idx].fill(wu.water);
+            }
+            water_update.extend(part.water_update.iter().cloned());
+        }
+    }
+}
+
+/// Divides `len` points into at most `threads` ranges of at least `MIN_PARTITION_LEN` points.
+/// Ranges end at ridge points - the highest ground around evenly spaced positions, where water flows across borders rarely.
+fn split_at_ridges(ground: &[PointHeight], threads: usize) -> Vec<Range<usize>> {
+    let len = ground.len();
+    let parts = threads.min(len / MIN_PARTITION_LEN).max(1);
+    let window = len / (4 * parts);
+    let mut ends = Vec::with_capacity(parts);
+    let mut start = 0;
+    for n in 1..parts {
+        let ideal = n * len / parts;
+        let ridge = (start.max(ideal - window)..ideal + window)
+            .max_by(|a, b| ground[*a].total_cmp(&ground[*b]))
+            .unwrap_or(ideal);
+        ends.push(start..ridge + 1);
+        start = ridge + 1;
+    }
+    ends.push(start..len);
+    ends
+}
+
+/// Calls `f` for every item - concurrently in `rayon` thread pool.
+#[cfg(feature = "parallel")]
+fn for_each_partition<I: Send>(items: Vec<I>, f: impl Fn(I) + Sync + Send) {
+    items.into_par_iter().for_each(f);
+}
+
+/// Calls `f` for every item - sequentially, as threads are available only with `parallel` feature.
+#[cfg(not(feature = "parallel"))]
+fn for_each_partition<I>(items: Vec<I>, f: impl Fn(I)) {
+    items.into_iter().for_each(f);
+}
+
+impl Partition {
+    /// Calculates flows of water from points of partition from levels at the beginning of iteration
+    /// (the same way as `simul_manual_1th_v2`).
+    fn calc<T: Topology>(&mut self, model: &Model<T>, dt: f64) {
+        self.water_update.clear();
+        for pi in &self.points_idx {
+            model.calc_flows(*pi, dt, &mut self.send_water_to, &mut self.water_update);
+        }
+    }
+
+    /// Applies flows calculated by `calc()` to `points` of partition.
+    /// Flows into points of other partitions are collected in `border_update`.
+    fn apply(&mut self, points: &mut [Point]) {
+        let first = self.range.start;
+        self.border_update.clear();
+        self.outflow = 0.0;
+        self.volume_moved = 0.0;
+        for wu in &self.water_update {
+            self.volume_moved += wu.water;
+            points[wu.from_idx - first].drain(wu.water);
+            if wu.to_idx == OUTSIDE {
+                self.outflow += wu.water;
+            } else if self.range.contains(&wu.to_idx) {
+                points[wu.to_idx - first].fill(wu.water);
+            } else {
+                self.border_update.push(wu.clone());
+            }
+        }
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////////////////////////
+// Tests
+//
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::*;
+    use crate::simul_manual_1th_v2;
+
+    /// Landscape big enough to be divided into several partitions.
+    fn random_points(len: usize) -> Vec<f64> {
+        use rand::{Rng, SeedableRng};
+        let mut rng = rand::rngs::StdRng::seed_from_u64(17);
+        (0..len).map(|_| rng.gen_range(0.0, 20.0)).collect()
+    }
+
+    /// Compares results with `simul_manual_1th_v2` - they should be equal within precision.
+    fn compare_with_v2(mut landscape: Landscape<impl Topology>, mut reference: simul_manual_1th_v2::Landscape<impl Topology>, steps: usize) {
+        for _ in 0..steps {
+            let expected = reference.rain_uniform(RAIN_DENSITY, true).unwrap().to_vec();
+            let result = landscape.rain_uniform(RAIN_DENSITY, true).unwrap();
+            for (i, (l, r)) in result.iter().zip(&expected).enumerate() {
+                assert!((l - r).abs() <= 2.0 * reference.precision(), "point {}: {} != {}", i, l, r);
+            }
+            assert!((landscape.outflow() - reference.outflow()).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn partitions() {
+        let ground = vec![1.0; 4 * MIN_PARTITION_LEN];
+        assert_eq!(split_at_ridges(&ground[..MIN_PARTITION_LEN], 8), vec![0..MIN_PARTITION_LEN]);
+        let mut ground = random_points(4 * MIN_PARTITION_LEN);
+        ground[2 * MIN_PARTITION_LEN + 100] = 50.0;
+        let ranges = split_at_ridges(&ground, 4);
+        assert_eq!(ranges.len(), 4);
+        assert_eq!(ranges[1].end, 2 * MIN_PARTITION_LEN + 101);
+        assert!(ranges.windows(2).all(|w| w[0].end == w[1].start && !w[0].is_empty()));
+        assert_eq!(ranges[3].end, ground.len());
+    }
+
+    #[test]
+    fn sv_case_sample() {
+        let mut landscape = Landscape::create(vec![3.0, 1.0, 6.0, 4.0, 8.0, 9.0]).unwrap();
+        assert_eq!(landscape.threads(), 1);
+        let prec = landscape.precision();
+        let result = landscape.rain_uniform(RAIN_DENSITY, true).unwrap();
+        for (l, r) in result.iter().zip(&[4.0, 4.0, 6.0, 6.0, 8.0, 9.0]) {
+            assert!((l - r).abs() < 6.0 * prec);
+        }
+    }
+
+    #[test]
+    fn threads() {
+        let mut landscape = Landscape::create(random_points(8 * MIN_PARTITION_LEN)).unwrap();
+        landscape.set_threads(4);
+        assert_eq!(landscape.threads(), if cfg!(feature = "parallel") { 4 } else { 1 });
+        landscape.set_threads(16);
+        assert_eq!(landscape.threads(), if cfg!(feature = "parallel") { 8 } else { 1 });
+    }
+
+    #[test]
+    fn sv_case_same_as_v2() {
+        let points = random_points(8 * MIN_PARTITION_LEN);
+        let mut landscape = Landscape::create(points.clone()).unwrap();
+        // Partitions are set directly, so that they are tested also without `parallel` feature.
+        landscape.set_partitions(split_at_ridges(&points, 4));
+        assert_eq!(landscape.threads(), 4);
+        landscape.set_drain(100);
+        landscape.set_verify(true).unwrap();
+        let mut reference = simul_manual_1th_v2::Landscape::create(points).unwrap();
+        reference.set_drain(100);
+        compare_with_v2(landscape, reference, 3);
+    }
+
+    #[test]
+    fn sv_case_border_flows() {
+        // Water flows from the first partition to the second one and back.
+        let points = vec![3.0, 1.0, 6.0, 4.0, 8.0, 9.0];
+        let mut landscape = Landscape::create(points.clone()).unwrap();
+        landscape.set_partitions(vec![0..3, 3..6]);
+        landscape.set_verify(true).unwrap();
+        compare_with_v2(landscape, simul_manual_1th_v2::Landscape::create(points).unwrap(), 3);
+    }
+}
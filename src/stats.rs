@@ -0,0 +1,55 @@
+/*!
+ * Simulation statistics - allow to profile algorithms (e.g. precision vs. performance).
+ */
+
+use std::fmt;
+use std::ops::AddAssign;
+use std::time::Duration;
+
+/// Statistics of water flow simulation (`stabilize_water()`) during one or more `rain()` calls.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Stats {
+    /// Number of iterations of the main loop (passes over all points).
+    pub iterations: usize,
+    /// Number of evaluations of points (checks whether point sends water to its neighbors).
+    pub visits: usize,
+    /// Number of applied flows of water from point to point (or outside of landscape).
+    pub updates: usize,
+    /// Total amount (volume) of water moved by flows.
+    pub volume_moved: f64,
+    /// Wall time of simulation.
+    pub duration: Duration,
+}
+
+impl AddAssign for Stats {
+    fn add_assign(&mut self, other: Stats) {
+        self.iterations += other.iterations;
+        self.visits += other.visits;
+        self.updates += other.updates;
+        self.volume_moved += other.volume_moved;
+        self.duration += other.duration;
+    }
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "iterations: {}, visits: {}, updates: {}, volume moved: {}, time: {:?}", self.iterations, self.visits, self.updates, self.volume_moved, self.duration)
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////////////////////////
+// Tests
+//
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn add() {
+        let mut total = Stats::default();
+        total += Stats { iterations: 3, visits: 12, updates: 5, volume_moved: 1.5, duration: Duration::from_millis(2) };
+        total += Stats { iterations: 1, visits: 4, updates: 0, volume_moved: 0.0, duration: Duration::from_millis(1) };
+        assert_eq!(total.to_string(), "iterations: 4, visits: 16, updates: 5, volume moved: 1.5, time: 3ms");
+    }
+}
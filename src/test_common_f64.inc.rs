@@ -6,10 +6,10 @@
             //let mut diff = 0.0;
             for (i, _r) in left.iter().enumerate() {
                 if (left[i] - right[i]).abs() > precision * (left.len() as PointHeight) {
-                    assert!(false, "left[{}]={} != right[{}]={}", i, left[i], i, right[i]);
+                    panic!("left[{}]={} != right[{}]={}", i, left[i], i, right[i]);
                 }
                 // if (left[i] - right[i]).abs() > prec {
-                //     assert!(false, "left[{}]={} != right[{}]={}", i, left[i], i, right[i]);
+                //     panic!("left[{}]={} != right[{}]={}", i, left[i], i, right[i]);
                 // }
                 // diff += (left[i] - right[i]).abs();
                 // if diff > prec * (left.len() as PointHeight) {
@@ -22,7 +22,7 @@
     #[test]
     fn sv_case_sample() {
         let points = vec![3.0, 1.0, 6.0, 4.0, 8.0, 9.0];
-        let mut landscape = Landscape::create(points);
+        let mut landscape = Landscape::create(points).unwrap();
         let prec = landscape.precision();
         let result = landscape.rain_uniform(RAIN_DENSITY, true).unwrap();
         compare(&prec, result, &[4.0, 4.0, 6.0, 6.0, 8.0, 9.0]);
@@ -31,7 +31,7 @@
     #[test]
     fn sv_case_mail2() {
         let points = vec![8.0, 8.0, 1.0];
-        let mut landscape = Landscape::create(points);
+        let mut landscape = Landscape::create(points).unwrap();
         let prec = landscape.precision();
         let result = landscape.rain_uniform(RAIN_DENSITY, true).unwrap();
         compare(&prec, result, &[8.0, 8.0, 4.0]);
@@ -40,7 +40,7 @@
     #[test]
     fn sv_case_mail3() {
         let points = vec![1.0, 8.0, 8.0, 1.0];
-        let mut landscape = Landscape::create(points);
+        let mut landscape = Landscape::create(points).unwrap();
         let prec = landscape.precision();
         let result = landscape.rain_uniform(RAIN_DENSITY, true).unwrap();
         compare(&prec, result, &[3.0, 8.0, 8.0, 3.0]);
@@ -49,7 +49,7 @@
     #[test]
     fn sv_case_mail4() {
         let points = vec![8.0, 4.0, 8.0, 8.0, 1.0];
-        let mut landscape = Landscape::create(points);
+        let mut landscape = Landscape::create(points).unwrap();
         let prec = landscape.precision();
         let result = landscape.rain_uniform(RAIN_DENSITY, true).unwrap();
         compare(&prec, result, &[8.0, 7.0, 8.0, 8.0, 3.0]);
@@ -58,7 +58,7 @@
     #[test]
     fn sv_case_mail5() {
         let points = vec![1.0, 8.0, 8.0, 8.0, 1.0];
-        let mut landscape = Landscape::create(points);
+        let mut landscape = Landscape::create(points).unwrap();
         let prec = landscape.precision();
         let result = landscape.rain_uniform(RAIN_DENSITY, true).unwrap();
         compare(&prec, result, &[3.5, 8.0, 8.0, 8.0, 3.5]);
@@ -67,7 +67,7 @@
     #[test]
     fn sv_case_sample_prec0() {
         let points = vec![3.0, 1.0, 6.0, 4.0, 8.0, 9.0];
-        let mut landscape = Landscape::create(points);
+        let mut landscape = Landscape::create(points).unwrap();
         let prec = landscape.precision();
         let result = landscape.rain_uniform(RAIN_DENSITY, true).unwrap();
         compare(&prec, result, &[4.0, 4.0, 6.0, 6.0, 8.0, 9.0]);
@@ -76,7 +76,7 @@
     #[test]
     fn sv_case_mail2_prec0() {
         let points = vec![8.0, 8.0, 1.0];
-        let mut landscape = Landscape::create_with_precision(points, 0.0);
+        let mut landscape = Landscape::create_with_precision(points, 0.0).unwrap();
         let prec = landscape.precision();
         let result = landscape.rain_uniform(RAIN_DENSITY, true).unwrap();
         compare(&prec, result, &[8.0, 8.0, 4.0]);
@@ -85,7 +85,7 @@
     #[test]
     fn sv_case_mail3_prec0() {
         let points = vec![1.0, 8.0, 8.0, 1.0];
-        let mut landscape = Landscape::create_with_precision(points, 0.0);
+        let mut landscape = Landscape::create_with_precision(points, 0.0).unwrap();
         let prec = landscape.precision();
         let result = landscape.rain_uniform(RAIN_DENSITY, true).unwrap();
         compare(&prec, result, &[3.0, 8.0, 8.0, 3.0]);
@@ -94,7 +94,7 @@
     #[test]
     fn sv_case_mail4_prec0() {
         let points = vec![8.0, 4.0, 8.0, 8.0, 1.0];
-        let mut landscape = Landscape::create_with_precision(points, 0.0);
+        let mut landscape = Landscape::create_with_precision(points, 0.0).unwrap();
         let prec = landscape.precision();
         let result = landscape.rain_uniform(RAIN_DENSITY, true).unwrap();
         compare(&prec, result, &[8.0, 7.0, 8.0, 8.0, 3.0]);
@@ -103,8 +103,85 @@
     #[test]
     fn sv_case_mail5_prec0() {
         let points = vec![1.0, 8.0, 8.0, 8.0, 1.0];
-        let mut landscape = Landscape::create_with_precision(points, 0.0);
+        let mut landscape = Landscape::create_with_precision(points, 0.0).unwrap();
         let prec = landscape.precision();
         let result = landscape.rain_uniform(RAIN_DENSITY, true).unwrap();
         compare(&prec, result, &[3.5, 8.0, 8.0, 8.0, 3.5]);
     }
+
+    #[test]
+    fn sv_case_losses() {
+        use crate::losses::Losses;
+        let mut landscape = Landscape::create(vec![3.0, 1.0, 6.0, 4.0, 8.0, 9.0]).unwrap();
+        let mut losses = Losses::new(6);
+        losses.set_infiltration(vec![0.0, 0.0, 0.0, 1.0, 0.0, 0.0], 0.5).unwrap();
+        losses.set_evaporation(0.25).unwrap();
+        landscape.set_losses(losses).unwrap();
+        let prec = landscape.precision();
+        let result = landscape.rain_uniform(RAIN_DENSITY, true).unwrap();
+        compare(&prec, result, &[3.0, 3.0, 6.0, 6.0, 8.0, 9.0]);
+        assert!((landscape.loss() - 2.0).abs() <= prec * 6.0);
+        // Dry step: water evaporates from both ponds, the rest of infiltration capacity is used.
+        let result = landscape.rain_uniform(0.0, true).unwrap();
+        compare(&prec, result, &[3.0, 2.75, 6.0, 5.25, 8.0, 9.0]);
+        assert!((landscape.loss() - 1.0).abs() <= prec * 6.0);
+    }
+
+    #[test]
+    fn sv_case_mass_balance() {
+        use crate::balance::MassLedger;
+        use crate::losses::Losses;
+        let mut landscape = Landscape::create(vec![3.0, 1.0, 6.0, 4.0, 8.0, 9.0]).unwrap();
+        landscape.set_drain(1);
+        let mut losses = Losses::new(6);
+        losses.set_evaporation(0.25).unwrap();
+        landscape.set_losses(losses).unwrap();
+        let mut ledger = MassLedger::new(landscape.stored());
+        for n in 0..10 {
+            landscape.rain(|i| (i + n % 3) as f64, false).unwrap();
+            ledger.record(landscape.time(), landscape.rain_added(), landscape.outflow(), landscape.loss(), landscape.stored());
+        }
+        assert!(ledger.entries().iter().all(|b| b.rain > 0.0 && b.outflow > 0.0 && b.loss > 0.0));
+        assert!(ledger.drift().abs() < 1e-9);
+    }
+
+    #[test]
+    fn sv_case_verify() {
+        let points: Vec<f64> = (0..200).map(|i| ((i * 37) % 23) as f64).collect();
+        let mut landscape = Landscape::create(points).unwrap();
+        landscape.set_drain(100);
+        landscape.set_verify(true).unwrap();
+        for _ in 0..5 {
+            landscape.rain_uniform(RAIN_DENSITY, false).unwrap();
+        }
+    }
+
+    #[test]
+    fn sv_case_negative_heights() {
+        // The same as sv_case_sample, but below sea level.
+        let points = vec![-97.0, -99.0, -94.0, -96.0, -92.0, -91.0];
+        let mut landscape = Landscape::create(points).unwrap();
+        landscape.set_verify(true).unwrap();
+        let prec = landscape.precision();
+        let result = landscape.rain_uniform(RAIN_DENSITY, true).unwrap();
+        compare(&prec, result, &[-96.0, -96.0, -94.0, -94.0, -92.0, -91.0]);
+    }
+
+    #[test]
+    fn sv_case_nan_height() {
+        use crate::error::Error;
+        assert!(matches!(Landscape::create(vec![3.0, f64::NAN, 6.0]), Err(Error::NonFiniteHeight { point: 1, .. })));
+        assert!(matches!(Landscape::create_with_topology(vec![3.0, 6.0], Line1D::new(3)), Err(Error::SizeMismatch { len: 2, expected: 3, .. })));
+    }
+
+    #[test]
+    fn sv_case_substeps() {
+        let points = vec![3.0, 1.0, 6.0, 4.0, 8.0, 9.0];
+        let mut landscape = Landscape::create(points).unwrap();
+        let prec = landscape.precision();
+        let result = landscape.rain_for(0.5, |_| RAIN_DENSITY, true).unwrap();
+        compare(&prec, result, &[3.0, 2.25, 6.0, 5.75, 8.0, 9.0]);
+        let result = landscape.rain_for(0.5, |_| RAIN_DENSITY, true).unwrap();
+        compare(&prec, result, &[4.0, 4.0, 6.0, 6.0, 8.0, 9.0]);
+        assert_eq!(landscape.time(), 1.0);
+    }
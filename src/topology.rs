@@ -0,0 +1,430 @@
+/*!
+ * Topologies of landscape - determine from which point to which water can flow.
+ * Algorithms in `simul_manual_*` modules are generic over `Topology` trait.
+ */
+
+use std::io::BufRead;
+use std::str::FromStr;
+
+use crate::error::{Error, Input, Result};
+
+/// Describes how points (sections) of landscape are connected.
+/// Points are identified by index in range `0..len()` - the same as index of point height passed to landscape constructor.
+/// Topology is shared between threads by multi-threaded solvers, so it must be `Sync`.
+pub trait Topology: Sync {
+    /// Iterator over indexes of neighbors of some point.
+    type Neighbors<'a>: Iterator<Item=usize> where Self: 'a;
+
+    /// Returns number of points in topology.
+    fn len(&self) -> usize;
+
+    /// Returns `true` if topology has no points.
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Determines directions in which water can flow from point at `idx` index.
+    fn neighbors(&self, idx: usize) -> Self::Neighbors<'_>;
+
+    /// Returns coordinates of point at `idx` index (if topology has any notion of coordinates).
+    fn coordinates(&self, _idx: usize) -> Option<Vec<usize>> {
+        None
+    }
+
+    /// Determines what happens with water flowing outside of landscape from point at `idx` index.
+    fn boundary(&self, _idx: usize) -> Boundary {
+        Boundary::Wall
+    }
+}
+
+/// Condition at the edge of landscape - determines what happens with water which could flow outside of landscape.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Boundary<H = f64> {
+    /// Infinite wall - water does not flow outside (as in problem description).
+    Wall,
+    /// Free outflow - water flows outside as to infinitely low neighbor and leaves the landscape.
+    Outflow,
+    /// Water body (e.g. river) with fixed level - water above this level flows into it and leaves the landscape.
+    /// Reservoir does not supply water into landscape.
+    Reservoir(H),
+}
+
+impl<H> Boundary<H> {
+    /// Converts level of reservoir to other type.
+    pub fn map<U>(self, f: impl FnOnce(H) -> U) -> Boundary<U> {
+        match self {
+            Boundary::Wall => Boundary::Wall,
+            Boundary::Outflow => Boundary::Outflow,
+            Boundary::Reservoir(h) => Boundary::Reservoir(f(h)),
+        }
+    }
+}
+
+impl FromStr for Boundary {
+    type Err = Error;
+
+    /// Parses boundary given as `wall`, `outflow` or `reservoir:LEVEL` (level must be finite).
+    fn from_str(s: &str) -> Result<Self> {
+        let mut parts = s.splitn(2, ':');
+        match (parts.next().unwrap_or_default(), parts.next()) {
+            ("wall", None) => Ok(Boundary::Wall),
+            ("outflow", None) => Ok(Boundary::Outflow),
+            ("reservoir", Some(level)) => match level.parse::<f64>() {
+                Ok(level) if level.is_finite() => Ok(Boundary::Reservoir(level)),
+                Ok(level) => Err(Error::InvalidArgument(format!("Reservoir level must be finite: {}", level))),
+                Err(e) => Err(Error::InvalidArgument(format!("Invalid reservoir level: {}", e))),
+            },
+            _ => Err(Error::InvalidArgument(format!("Unknown boundary: {} (expected one of: wall, outflow, reservoir:LEVEL)", s))),
+        }
+    }
+}
+
+/// Returns first of given boundaries which is not a wall (or wall if all of them are walls).
+fn open_boundary(boundaries: &[Boundary]) -> Boundary {
+    boundaries.iter().copied().find(|b| *b != Boundary::Wall).unwrap_or(Boundary::Wall)
+}
+
+/// Points placed on the line (as in problem description), by default with infinite walls at both ends.
+#[derive(Debug, Clone)]
+pub struct Line1D {
+    len: usize,
+    left: Boundary,
+    right: Boundary,
+}
+
+impl Line1D {
+    /// Creates line of `len` points.
+    pub fn new(len: usize) -> Self {
+        Line1D { len, left: Boundary::Wall, right: Boundary::Wall }
+    }
+
+    /// Sets conditions at the left (before first point) and the right (after last point) ends of line.
+    pub fn set_boundaries(&mut self, left: Boundary, right: Boundary) {
+        self.left = left;
+        self.right = right;
+    }
+}
+
+impl Topology for Line1D {
+    type Neighbors<'a> = Iter1D;
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn neighbors(&self, idx: usize) -> Iter1D {
+        Iter1D {idx, max:self.len, iter:0}
+    }
+
+    fn coordinates(&self, idx: usize) -> Option<Vec<usize>> {
+        Some(vec![idx])
+    }
+
+    fn boundary(&self, idx: usize) -> Boundary {
+        match (idx == 0, idx + 1 == self.len) {
+            (true, true) => open_boundary(&[self.left, self.right]),
+            (true, false) => self.left,
+            (false, true) => self.right,
+            (false, false) => Boundary::Wall,
+        }
+    }
+}
+
+/// Iterator over neighbors of point on the line.
+pub struct Iter1D {
+    idx: usize,
+    max: usize,
+    iter: u8,
+}
+
+impl Iterator for Iter1D {
+    type Item = usize;
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.iter {
+            0 => {
+                self.iter = 1;
+                if self.idx > 0 { Some(self.idx - 1) } else if self.max > 1 { Some(1) } else { None }
+            },
+            1 => {
+                self.iter = 2;
+                if self.idx == 0 || self.idx >= self.max - 1 { None } else { Some(self.idx + 1) }
+            },
+            _ => None
+        }
+    }
+}
+
+/// Neighbourhood of point on the 2D grid.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Neighborhood {
+    /// 4 neighbors - points sharing an edge with the point.
+    VonNeumann,
+    /// 8 neighbors - points sharing an edge or a corner with the point.
+    Moore,
+}
+
+/// Relative positions (row, column) of neighbors in von Neumann neighbourhood.
+const VON_NEUMANN_OFFSETS: [(isize, isize); 4] = [(-1, 0), (0, -1), (0, 1), (1, 0)];
+/// Relative positions (row, column) of neighbors in Moore neighbourhood.
+const MOORE_OFFSETS: [(isize, isize); 8] = [(-1, -1), (-1, 0), (-1, 1), (0, -1), (0, 1), (1, -1), (1, 0), (1, 1)];
+
+/// Points placed on the 2D grid, by default with infinite walls around it.
+/// Points are indexed row by row, i.e. point in `row` and `col` has index `row * cols + col`.
+#[derive(Debug, Clone)]
+pub struct Grid2D {
+    rows: usize,
+    cols: usize,
+    neighborhood: Neighborhood,
+    /// Conditions at top, right, bottom and left edge of grid.
+    boundaries: [Boundary; 4],
+}
+
+impl Grid2D {
+    /// Creates grid of `rows` x `cols` points.
+    pub fn new(rows: usize, cols: usize, neighborhood: Neighborhood) -> Self {
+        Grid2D { rows, cols, neighborhood, boundaries: [Boundary::Wall; 4] }
+    }
+
+    /// Sets conditions at the edges of grid.
+    /// Corner points use the first open (not wall) boundary in order: top, right, bottom, left.
+    pub fn set_boundaries(&mut self, top: Boundary, right: Boundary, bottom: Boundary, left: Boundary) {
+        self.boundaries = [top, right, bottom, left];
+    }
+
+    /// Returns number of rows.
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    /// Returns number of columns.
+    pub fn cols(&self) -> usize {
+        self.cols
+    }
+}
+
+impl Topology for Grid2D {
+    type Neighbors<'a> = IterGrid;
+
+    fn len(&self) -> usize {
+        self.rows * self.cols
+    }
+
+    fn neighbors(&self, idx: usize) -> IterGrid {
+        let offsets: &'static [(isize, isize)] = match self.neighborhood {
+            Neighborhood::VonNeumann => &VON_NEUMANN_OFFSETS,
+            Neighborhood::Moore => &MOORE_OFFSETS,
+        };
+        IterGrid { row: idx / self.cols, col: idx % self.cols, rows: self.rows, cols: self.cols, offsets, iter: 0 }
+    }
+
+    fn coordinates(&self, idx: usize) -> Option<Vec<usize>> {
+        Some(vec![idx / self.cols, idx % self.cols])
+    }
+
+    fn boundary(&self, idx: usize) -> Boundary {
+        let (row, col) = (idx / self.cols, idx % self.cols);
+        let [top, right, bottom, left] = self.boundaries;
+        let edge = |at_edge: bool, b: Boundary| if at_edge { b } else { Boundary::Wall };
+        open_boundary(&[
+            edge(row == 0, top),
+            edge(col + 1 == self.cols, right),
+            edge(row + 1 == self.rows, bottom),
+            edge(col == 0, left),
+        ])
+    }
+}
+
+/// Iterator over neighbors of point on the 2D grid.
+pub struct IterGrid {
+    row: usize,
+    col: usize,
+    rows: usize,
+    cols: usize,
+    offsets: &'static [(isize, isize)],
+    iter: usize,
+}
+
+impl Iterator for IterGrid {
+    type Item = usize;
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.iter < self.offsets.len() {
+            let (dr, dc) = self.offsets[self.iter];
+            self.iter += 1;
+            let row = self.row as isize + dr;
+            let col = self.col as isize + dc;
+            if row >= 0 && col >= 0 && (row as usize) < self.rows && (col as usize) < self.cols {
+                return Some(row as usize * self.cols + col as usize);
+            }
+        }
+        None
+    }
+}
+
+/// Arbitrary topology - every point has its own list of neighbors.
+/// Allows to model irregular catchments, channels or tunnels connecting non-adjacent points.
+#[derive(Debug, Clone)]
+pub struct Graph {
+    neighbors: Vec<Vec<usize>>,
+    /// Condition at the edge of landscape next to every point (wall for points not connected with outside).
+    boundaries: Vec<Boundary>,
+}
+
+impl Graph {
+    /// Creates graph of `len` points without any connections (edges).
+    pub fn new(len: usize) -> Self {
+        Graph { neighbors: vec![Vec::with_capacity(2); len], boundaries: vec![Boundary::Wall; len] }
+    }
+
+    /// Sets condition at the edge of landscape next to point at `idx` index (by default it is wall).
+    /// Panics if index is out of range.
+    pub fn set_boundary(&mut self, idx: usize, boundary: Boundary) {
+        self.boundaries[idx] = boundary;
+    }
+
+    /// Connects points at `a` and `b` indexes, so that water can flow between them in both directions.
+    /// Adding the same edge again has no effect.
+    /// Panics if any of indexes is out of range.
+    pub fn add_edge(&mut self, a: usize, b: usize) {
+        if a == b || self.neighbors[a].contains(&b) {
+            return;
+        }
+        self.neighbors[a].push(b);
+        self.neighbors[b].push(a);
+    }
+
+    /// Creates graph of `len` points with edges read from `reader`.
+    /// Every line contains one edge: indexes (counted from 0) of two connected points separated by spaces or comma.
+    /// Empty lines are ignored.
+    pub fn read_edges(reader: impl BufRead, len: usize) -> Result<Self> {
+        let mut graph = Self::new(len);
+        for (n, line) in reader.lines().enumerate() {
+            let line = match line {
+                Ok(line) => line,
+                Err(e) => return Err(Error::parse(Input::Edges, n + 1, e)),
+            };
+            let mut idx = Vec::with_capacity(2);
+            for v in line.split(|c: char| c == ',' || c.is_whitespace()).filter(|v| !v.is_empty()) {
+                match v.parse::<usize>() {
+                    Ok(i) if i < len => idx.push(i),
+                    Ok(i) => return Err(Error::PointOutOfRange { input: Input::Edges, line: n + 1, index: i, len }),
+                    Err(e) => return Err(Error::parse(Input::Edges, n + 1, e)),
+                }
+            }
+            match idx[..] {
+                [] => continue,
+                [a, b] if a != b => graph.add_edge(a, b),
+                [_, _] => return Err(Error::Format { input: Input::Edges, line: n + 1, message: "point can not be connected to itself".into() }),
+                _ => return Err(Error::Format { input: Input::Edges, line: n + 1, message: format!("expected 2 point indexes, found {}", idx.len()) }),
+            }
+        }
+        Ok(graph)
+    }
+}
+
+impl Topology for Graph {
+    type Neighbors<'a> = std::iter::Copied<std::slice::Iter<'a, usize>>;
+
+    fn len(&self) -> usize {
+        self.neighbors.len()
+    }
+
+    fn neighbors(&self, idx: usize) -> Self::Neighbors<'_> {
+        self.neighbors[idx].iter().copied()
+    }
+
+    fn boundary(&self, idx: usize) -> Boundary {
+        self.boundaries[idx]
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////////////////////////
+// Tests
+//
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn neighbors<T: Topology>(topology: &T, idx: usize) -> Vec<usize> {
+        topology.neighbors(idx).collect()
+    }
+
+    #[test]
+    fn line_neighbors() {
+        let line = Line1D::new(3);
+        assert_eq!(neighbors(&line, 0), vec![1]);
+        assert_eq!(neighbors(&line, 1), vec![0, 2]);
+        assert_eq!(neighbors(&line, 2), vec![1]);
+        assert_eq!(neighbors(&Line1D::new(1), 0), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn grid_neighbors() {
+        let grid = Grid2D::new(2, 3, Neighborhood::VonNeumann);
+        assert_eq!(grid.len(), 6);
+        assert_eq!(neighbors(&grid, 0), vec![1, 3]);
+        assert_eq!(neighbors(&grid, 1), vec![0, 2, 4]);
+        assert_eq!(neighbors(&grid, 5), vec![2, 4]);
+        assert_eq!(grid.coordinates(5), Some(vec![1, 2]));
+
+        let grid = Grid2D::new(3, 3, Neighborhood::Moore);
+        assert_eq!(neighbors(&grid, 0), vec![1, 3, 4]);
+        assert_eq!(neighbors(&grid, 4), vec![0, 1, 2, 3, 5, 6, 7, 8]);
+        assert_eq!(neighbors(&grid, 8), vec![4, 5, 7]);
+    }
+
+    #[test]
+    fn graph_neighbors() {
+        let graph = Graph::read_edges("0 1\n\n1,2\n0 2\n2 0\n".as_bytes(), 4).unwrap();
+        assert_eq!(graph.len(), 4);
+        assert_eq!(neighbors(&graph, 0), vec![1, 2]);
+        assert_eq!(neighbors(&graph, 2), vec![1, 0]);
+        assert_eq!(neighbors(&graph, 3), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn graph_invalid_edges() {
+        let err = Graph::read_edges("0 1\n1 4\n".as_bytes(), 4).unwrap_err();
+        assert_eq!(err.to_string(), "Edges line number 2: point index 4 out of range (landscape has 4 points)");
+        let err = Graph::read_edges("0 1 2\n".as_bytes(), 4).unwrap_err();
+        assert_eq!(err.to_string(), "Edges line number 1: expected 2 point indexes, found 3");
+        let err = Graph::read_edges("0 1\n2 2\n".as_bytes(), 4).unwrap_err();
+        assert_eq!(err.to_string(), "Edges line number 2: point can not be connected to itself");
+        assert!(Graph::read_edges("0 -1\n".as_bytes(), 4).is_err());
+    }
+
+    #[test]
+    fn boundaries() {
+        let mut line = Line1D::new(3);
+        line.set_boundaries(Boundary::Outflow, Boundary::Reservoir(2.0));
+        assert_eq!(line.boundary(0), Boundary::Outflow);
+        assert_eq!(line.boundary(1), Boundary::Wall);
+        assert_eq!(line.boundary(2), Boundary::Reservoir(2.0));
+
+        let mut grid = Grid2D::new(3, 3, Neighborhood::VonNeumann);
+        grid.set_boundaries(Boundary::Wall, Boundary::Outflow, Boundary::Wall, Boundary::Wall);
+        assert_eq!(grid.boundary(2), Boundary::Outflow);
+        assert_eq!(grid.boundary(5), Boundary::Outflow);
+        assert_eq!(grid.boundary(4), Boundary::Wall);
+        assert_eq!(grid.boundary(6), Boundary::Wall);
+
+        let mut graph = Graph::new(3);
+        graph.set_boundary(1, Boundary::Reservoir(1.0));
+        graph.set_boundary(1, Boundary::Outflow);
+        assert_eq!(graph.boundary(0), Boundary::Wall);
+        assert_eq!(graph.boundary(1), Boundary::Outflow);
+        graph.set_boundary(1, Boundary::Wall);
+        assert_eq!(graph.boundary(1), Boundary::Wall);
+    }
+
+    #[test]
+    fn parse_boundary() {
+        assert_eq!("wall".parse::<Boundary>(), Ok(Boundary::Wall));
+        assert_eq!("outflow".parse::<Boundary>(), Ok(Boundary::Outflow));
+        assert_eq!("reservoir:-2.5".parse::<Boundary>(), Ok(Boundary::Reservoir(-2.5)));
+        assert!("reservoir".parse::<Boundary>().is_err());
+        assert!("reservoir:inf".parse::<Boundary>().is_err());
+        assert!("outflow:1".parse::<Boundary>().is_err());
+        assert!("river".parse::<Boundary>().is_err());
+    }
+}
@@ -0,0 +1,160 @@
+/*!
+ * Verification mode - runtime checks of invariants of simulation (enabled by `Solver::set_verify()`).
+ */
+
+use std::error;
+use std::fmt;
+
+use crate::error::{Error, Result};
+
+/// Exponent used in state function: `area * (level - base)^STATE_EXP` summed over all points.
+/// State function is strictly convex, so every flow of water from higher level to lower one decreases it.
+const STATE_EXP: f64 = 1.4;
+
+/// Relative tolerance of mass conservation check for solvers which calculate on `f64`.
+pub const VOLUME_TOLERANCE: f64 = 1e-9;
+
+/// Returns contribution of point with given water `level` and `area` to state function.
+/// `base` is the lowest ground level of landscape (so that state function is defined for all levels).
+pub fn state(level: f64, area: f64, base: f64) -> f64 {
+    area * (level - base).max(0.0).powf(STATE_EXP)
+}
+
+/// Broken invariant of simulation.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Violation {
+    /// State function did not decrease in iteration of main loop - water levels would never stabilize.
+    StateNotDecreasing { before: f64, after: f64 },
+    /// State function is lower than state of dry landscape.
+    StateBelowBound { state: f64, bound: f64 },
+    /// Amount of water over point is negative.
+    NegativeWater { point: usize, water: f64 },
+    /// Water stored in landscape plus outflow differs from water before simulation.
+    MassNotConserved { expected: f64, actual: f64 },
+    /// Simulation finished, but water could still flow from point to its lower neighbor.
+    UnstableWater { point: usize, level: f64, neighbor: usize, neighbor_level: f64 },
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Violation::StateNotDecreasing { before, after } =>
+                write!(f, "state function did not decrease: {} -> {}", before, after),
+            Violation::StateBelowBound { state, bound } =>
+                write!(f, "state function {} is below its lower bound {}", state, bound),
+            Violation::NegativeWater { point, water } =>
+                write!(f, "negative amount of water {} at point {}", water, point),
+            Violation::MassNotConserved { expected, actual } =>
+                write!(f, "water volume with outflow {} differs from expected {} by {:e}", actual, expected, actual - expected),
+            Violation::UnstableWater { point, level, neighbor, neighbor_level } =>
+                write!(f, "water at point {} (level {}) can flow to point {} (level {})", point, level, neighbor, neighbor_level),
+        }
+    }
+}
+
+/// Flow of water applied in iteration in which invariant was broken.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Flow {
+    pub from: usize,
+    /// Index of neighbor (`None` if water flowed outside of landscape).
+    pub to: Option<usize>,
+    pub volume: f64,
+}
+
+/// Diagnostic report of failed verification - returned (as `Error::Verification`) by `rain()` of solver in verification mode.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Report {
+    /// Simulated time (in hours) at the beginning of `rain()` call.
+    pub time: f64,
+    /// Iteration of main loop in which invariants were broken (0 - checks done after simulation).
+    pub iteration: usize,
+    pub violations: Vec<Violation>,
+    /// Offending flows applied in the iteration (if solver simulates water flow as separate flows).
+    pub flows: Vec<Flow>,
+}
+
+impl Report {
+    /// Creates empty report of checks done at given `time` and `iteration`.
+    pub fn new(time: f64, iteration: usize) -> Self {
+        Report { time, iteration, violations: Vec::new(), flows: Vec::new() }
+    }
+
+    /// Returns `true` if no invariant was broken.
+    pub fn is_ok(&self) -> bool {
+        self.violations.is_empty()
+    }
+
+    /// Returns points mentioned by violations and flows (sorted, without duplicates).
+    pub fn points(&self) -> Vec<usize> {
+        let mut points: Vec<usize> = self.violations.iter().filter_map(|v| match v {
+            Violation::NegativeWater { point, .. } | Violation::UnstableWater { point, .. } => Some(*point),
+            _ => None,
+        }).chain(self.flows.iter().flat_map(|f| std::iter::once(f.from).chain(f.to))).collect();
+        points.sort_unstable();
+        points.dedup();
+        points
+    }
+
+    /// Returns `Ok` if no invariant was broken, otherwise report as `Error::Verification`.
+    pub fn into_result(self) -> Result<()> {
+        if self.is_ok() { Ok(()) } else { Err(Error::Verification(self)) }
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Verification failed at time {}", self.time)?;
+        if self.iteration > 0 {
+            write!(f, ", iteration {}", self.iteration)?;
+        }
+        write!(f, ":")?;
+        for v in &self.violations {
+            write!(f, "\n  - {}", v)?;
+        }
+        if !self.flows.is_empty() {
+            write!(f, "\n  offending points: {:?}", self.points())?;
+            write!(f, "\n  offending flows:")?;
+            for flow in &self.flows {
+                match flow.to {
+                    Some(to) => write!(f, "\n    {} -> {}: {}", flow.from, to, flow.volume)?,
+                    None => write!(f, "\n    {} -> outside: {}", flow.from, flow.volume)?,
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl error::Error for Report {}
+
+///////////////////////////////////////////////////////////////////////////////////////////////////
+// Tests
+//
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn report() {
+        let mut report = Report::new(2.0, 3);
+        assert!(report.clone().into_result().is_ok());
+        report.violations.push(Violation::NegativeWater { point: 4, water: -0.5 });
+        report.flows.push(Flow { from: 1, to: Some(0), volume: 0.25 });
+        report.flows.push(Flow { from: 4, to: None, volume: 1.0 });
+        assert_eq!(report.points(), vec![0, 1, 4]);
+        assert_eq!(report.to_string(), "Verification failed at time 2, iteration 3:\n  \
+            - negative amount of water -0.5 at point 4\n  \
+            offending points: [0, 1, 4]\n  \
+            offending flows:\n    1 -> 0: 0.25\n    4 -> outside: 1");
+        assert_eq!(report.clone().into_result(), Err(Error::Verification(report)));
+    }
+
+    #[test]
+    fn state_decreases() {
+        // Moving water from higher to lower point decreases state.
+        let before = state(3.0, 1.0, 0.0) + state(1.0, 1.0, 0.0);
+        let after = state(2.5, 1.0, 0.0) + state(1.5, 1.0, 0.0);
+        assert!(after < before);
+    }
+}
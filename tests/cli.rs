@@ -0,0 +1,123 @@
+/*!
+ * Tests of command line program.
+ */
+
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// Landscape from problem description.
+const SAMPLE: &str = "3\n1\n6\n4\n8\n9\n";
+
+/// Runs program with given arguments and `input` on stdin, returns exit status, stdout and stderr.
+fn run(args: &[&str], input: &str) -> (bool, String, String) {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_sv_challenge"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Can not run program");
+    // Program may fail before reading its input.
+    let _ = child.stdin.take().unwrap().write_all(input.as_bytes());
+    let output = child.wait_with_output().unwrap();
+    (output.status.success(), String::from_utf8_lossy(&output.stdout).into(), String::from_utf8_lossy(&output.stderr).into())
+}
+
+/// Checks that program fails and error message contains `message`.
+fn assert_rejected(args: &[&str], message: &str) {
+    let (ok, stdout, stderr) = run(args, SAMPLE);
+    assert!(!ok, "{:?} accepted, output: {}", args, stdout);
+    assert!(stderr.contains(message), "{:?}: unexpected error: {}", args, stderr);
+}
+
+#[test]
+fn sample() {
+    let (ok, stdout, _) = run(&["1", "-s", "v3"], SAMPLE);
+    assert!(ok);
+    assert_eq!(stdout, "4, 4, 6, 6, 8, 9\n");
+}
+
+#[test]
+fn invalid_rain() {
+    for rain in &["--rain=-1", "--rain=NaN", "--rain=inf"] {
+        for solver in &["v2", "v3"] {
+            assert_rejected(&["1", rain, "-s", solver], "Invalid value of rain");
+        }
+    }
+    let (ok, stdout, _) = run(&["1", "--rain=0", "-s", "v3"], SAMPLE);
+    assert!(ok);
+    assert_eq!(stdout, "3, 1, 6, 4, 8, 9\n");
+}
+
+#[test]
+fn invalid_precision() {
+    for precision in &["--precision=-1", "--precision=0", "--precision=NaN", "--precision=inf"] {
+        assert_rejected(&["1", precision], "Invalid value of precision");
+    }
+    let (ok, _, _) = run(&["1", "--precision=0.001"], SAMPLE);
+    assert!(ok);
+}
+
+#[test]
+fn invalid_max_drift() {
+    for drift in &["--max-drift=-1", "--max-drift=NaN"] {
+        assert_rejected(&["1", drift], "Invalid value of max-drift");
+    }
+    let (ok, _, _) = run(&["1", "--max-drift=0"], SAMPLE);
+    assert!(ok);
+}
+
+#[test]
+fn invalid_losses() {
+    for args in &[["--evaporation=-1", "--infiltration=0"], ["--infiltration=-2", "--capacity=1"], ["--infiltration=NaN", "--capacity=1"],
+                  ["--capacity=-1", "--infiltration=1"], ["--capacity=inf", "--infiltration=1"]] {
+        let name = args[0][2..].split('=').next().unwrap();
+        for solver in &["v2", "v3"] {
+            assert_rejected(&["1", args[0], args[1], "-s", solver], &format!("Invalid value of {}", name));
+        }
+    }
+    let path = std::env::temp_dir().join(format!("sv_challenge_capacity_{}.txt", std::process::id()));
+    std::fs::write(&path, "1\n1\n-1\n1\n1\n1\n").unwrap();
+    let capacity_file = format!("--capacity-file={}", path.display());
+    assert_rejected(&["1", "--infiltration=1", &capacity_file], "line number 3");
+    std::fs::remove_file(&path).unwrap();
+    let (ok, stdout, _) = run(&["1", "--evaporation=0.25", "-s", "v3"], SAMPLE);
+    assert!(ok);
+    assert_eq!(stdout, "3.25, 3.25, 6, 6, 8, 9\n");
+}
+
+#[test]
+fn at() {
+    for at in &["--at=-1", "--at=NaN", "--at=inf"] {
+        for solver in &["v2", "ev", "v3"] {
+            assert_rejected(&[at, "-s", solver], "Invalid value of at");
+        }
+    }
+    // STEPS is ignored, but it must be given before TOPOLOGY.
+    for args in &[&["--at=3"][..], &["--at=3", "5"], &["--at=3", "0", "line"]] {
+        let (ok, stdout, _) = run(&[*args, &["-s", "ev"]].concat(), "8\n8\n1\n");
+        assert!(ok, "{:?}", args);
+        assert_eq!(stdout, "8.666666666666666, 8.666666666666666, 8.666666666666666\n", "{:?}", args);
+    }
+    let (ok, stdout, _) = run(&["--at=1", "0", "grid4", "-s", "v3"], "3 3 3\n3 0 3\n3 3 3\n");
+    assert!(ok);
+    assert_eq!(stdout, "3.6666666666666665, 3.6666666666666665, 3.6666666666666665\n".repeat(3));
+}
+
+#[test]
+fn boundaries() {
+    for (args, expected) in &[(&["--boundary=left=outflow"][..], "3, 3, 6, 6, 8, 9\n"),
+                              (&["--boundary=all=outflow", "--boundary=right=wall"][..], "3, 3, 6, 6, 8, 9\n"),
+                              (&["--drain=1"][..], "3, 1, 6, 6, 8, 9\n"),
+                              (&["--drain=1", "--drain=3"][..], "3, 1, 6, 4, 8, 9\n")] {
+        let (ok, stdout, stderr) = run(&[&["1", "-s", "v3"], *args].concat(), SAMPLE);
+        assert!(ok, "{:?}: {}", args, stderr);
+        assert_eq!(&stdout, expected, "{:?}", args);
+    }
+    assert_rejected(&["1", "--boundary=left=river"], "Invalid value of boundary");
+    assert_rejected(&["1", "--boundary=left=reservoir:NaN"], "Reservoir level must be finite");
+    assert_rejected(&["1", "--boundary=left"], "expected SIDE=BOUNDARY");
+    assert_rejected(&["1", "--boundary=top=outflow"], "Invalid side of boundary: top");
+    assert_rejected(&["1", "--drain=6"], "Invalid drain point: 6");
+    assert_rejected(&["1", "--drain=-1"], "Invalid value of drain");
+}